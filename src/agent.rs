@@ -1,116 +1,557 @@
-use crate::game::Board;
-use rand::{prelude::*, seq::SliceRandom};
-use std::collections::HashMap;
-use std::fs::File;
-use std::io::{Read, Write};
+use crate::error::Error;
+use crate::exploration::{Decay, Exploration, Strategy};
+use crate::game::GameState;
+use crate::player::{Learner, Player};
+use crate::seed;
+use crate::storage::{self, Storage};
+use rand::rngs::StdRng;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{self, File};
+use std::io::{ErrorKind, Read};
+use std::path::Path;
+use std::str::FromStr;
 
-#[derive(Debug, Clone)]
-pub struct Agent {
-    name: String,
-    states: Vec<String>,
-    lr: f32,
-    pub states_values: HashMap<String, f32>,
-    exp_rate: f32,
+/// What a learning agent learns and how it backs up rewards.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Algorithm {
+    /// Values of the positions after the agent's moves, backed up through
+    /// the whole game once it ends.
+    Afterstate,
+    /// Values of each move in each position, updated after every move
+    /// towards the best move in the next position.
+    QLearning,
+    /// Like `QLearning`, but updated towards the move actually played next.
+    Sarsa,
 }
 
-impl Agent {
-    pub fn new(name: &str) -> Self {
+impl FromStr for Algorithm {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "afterstate" => Ok(Algorithm::Afterstate),
+            "q-learning" => Ok(Algorithm::QLearning),
+            "sarsa" => Ok(Algorithm::Sarsa),
+            _ => Err(format!("Unknown algorithm {}", s)),
+        }
+    }
+}
+
+/// The hyperparameters of a learning agent, saved along with what it learned.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AgentConfig {
+    pub algorithm: Algorithm,
+    /// How far each value moves towards the value backed up into it.
+    pub learning_rate: f32,
+    /// How the agent strays from its best move to discover new ones.
+    pub exploration: Exploration,
+    /// How much of a position's value is passed on to the one before it.
+    pub discount: f32,
+    /// How far back each update reaches in the afterstate algorithm: below 1
+    /// values are updated after every move with eligibility traces, TD(λ),
+    /// and 1 backs the reward up through the game once it ends.
+    pub lambda: f32,
+    pub win_reward: f32,
+    /// The reward for a draw played as O, see `x_draw_reward`.
+    pub draw_reward: f32,
+    /// The reward for a draw played as X. By default X learns nothing from
+    /// its draws and only O, which holds the draw while moving second, is
+    /// rewarded for them.
+    pub x_draw_reward: Option<f32>,
+    pub loss_reward: f32,
+    /// The value of positions the agent has not seen yet.
+    pub initial_value: f32,
+    /// Stores values under `GameState::get_canonical_hash`, so that all the
+    /// rotations and reflections of a position share one entry.
+    pub symmetric: bool,
+    /// The seed of the run, from which each agent derives its own by name so
+    /// that its games can be reproduced.
+    pub seed: Option<u64>,
+}
+
+impl Default for AgentConfig {
+    fn default() -> Self {
         Self {
-            name: name.to_string(),
-            states: vec![],
-            lr: 0.2,
-            states_values: HashMap::new(),
-            exp_rate: 0.1,
+            algorithm: Algorithm::Afterstate,
+            learning_rate: 0.2,
+            exploration: Exploration::default(),
+            discount: 1.0,
+            lambda: 1.0,
+            win_reward: 1.0,
+            draw_reward: 0.5,
+            x_draw_reward: None,
+            loss_reward: -1.0,
+            initial_value: 0.0,
+            symmetric: false,
+            seed: None,
         }
     }
+}
 
-    pub fn save_model(&self) -> Result<(), String> {
-        let filename = format!("data/policy_{}", self.name);
-        let serialized_string = match serde_json::to_string(&self.states_values) {
-            Ok(v) => v,
-            Err(e) => return Err(e.to_string()),
-        };
+impl AgentConfig {
+    /// The reward for a game that ended with `winner` played as `me`, `None`
+    /// if the game teaches nothing.
+    pub(crate) fn reward(&self, winner: Option<u8>, me: u8) -> Option<f32> {
+        match winner {
+            Some(winner) if winner == me => Some(self.win_reward),
+            Some(_) => Some(self.loss_reward),
+            None if me == b'O' => Some(self.draw_reward),
+            None => self.x_draw_reward,
+        }
+    }
+}
 
-        let mut file = match File::create(filename.clone()) {
-            Ok(f) => f,
-            Err(e) => return Err(e.to_string()),
-        };
+/// The hyperparameters picked for a run, each `None` unless it was given.
+/// They are applied over the defaults for a new agent and over the config a
+/// trained agent was saved with, which keeps the others.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct AgentOptions {
+    pub algorithm: Option<Algorithm>,
+    pub learning_rate: Option<f32>,
+    pub exploration: ExplorationOptions,
+    pub discount: Option<f32>,
+    pub lambda: Option<f32>,
+    pub win_reward: Option<f32>,
+    pub draw_reward: Option<f32>,
+    pub x_draw_reward: Option<f32>,
+    pub loss_reward: Option<f32>,
+    pub initial_value: Option<f32>,
+    pub symmetric: Option<bool>,
+    pub seed: Option<u64>,
+}
+
+/// The parts of `Exploration` picked for a run, see `AgentOptions`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct ExplorationOptions {
+    pub strategy: Option<Strategy>,
+    pub decay: Option<Decay>,
+    pub start: Option<f32>,
+    pub end: Option<f32>,
+}
 
-        match file.write_all(serialized_string.as_bytes()) {
-            Ok(_) => println!("Successfully saved to {}", filename),
-            Err(e) => return Err(e.to_string()),
+impl AgentOptions {
+    /// Reads options from a JSON file with the fields of `AgentConfig`, any
+    /// of which may be left out.
+    pub fn load(path: &Path) -> Result<Self, Error> {
+        Ok(serde_json::from_str(&fs::read_to_string(path)?)?)
+    }
+
+    /// The config of a new agent: the defaults with the options given.
+    pub fn config(&self) -> AgentConfig {
+        self.apply(AgentConfig::default())
+    }
+
+    /// `config` with the options given replacing its values.
+    pub fn apply(&self, config: AgentConfig) -> AgentConfig {
+        let exploration = &self.exploration;
+
+        AgentConfig {
+            algorithm: self.algorithm.unwrap_or(config.algorithm),
+            learning_rate: self.learning_rate.unwrap_or(config.learning_rate),
+            exploration: Exploration {
+                strategy: exploration.strategy.unwrap_or(config.exploration.strategy),
+                decay: exploration.decay.unwrap_or(config.exploration.decay),
+                start: exploration.start.unwrap_or(config.exploration.start),
+                end: exploration.end.unwrap_or(config.exploration.end),
+            },
+            discount: self.discount.unwrap_or(config.discount),
+            lambda: self.lambda.unwrap_or(config.lambda),
+            win_reward: self.win_reward.unwrap_or(config.win_reward),
+            draw_reward: self.draw_reward.unwrap_or(config.draw_reward),
+            x_draw_reward: self.x_draw_reward.or(config.x_draw_reward),
+            loss_reward: self.loss_reward.unwrap_or(config.loss_reward),
+            initial_value: self.initial_value.unwrap_or(config.initial_value),
+            symmetric: self.symmetric.unwrap_or(config.symmetric),
+            seed: self.seed.or(config.seed),
         }
+    }
+}
+
+/// The version of the model files `write_model` saves. Files saved before
+/// versions were recorded have none, and hold the bare map of values.
+const MODEL_FORMAT: u32 = 1;
 
-        Ok(())
+/// How much an agent was trained, saved with its model.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TrainingStats {
+    /// The training games played, over all of its runs.
+    pub cycles: u64,
+    /// How many training runs it went through.
+    pub runs: u32,
+}
+
+/// What a model file records besides the values, which agents keep to save
+/// it again.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ModelInfo {
+    /// The game the values are for, see `GameState::variant`, `None` for
+    /// models saved before it was recorded.
+    pub variant: Option<String>,
+    /// When the agent was created, in UTC, `None` for models saved before it
+    /// was recorded.
+    pub created: Option<String>,
+    /// The config of the last run that trained the agent, including its
+    /// algorithm and seed, `None` for models saved before configs.
+    pub config: Option<AgentConfig>,
+    pub stats: TrainingStats,
+}
+
+/// What is written to `policy_{name}` in the models directory, generic so
+/// that it can be saved from a sorted map of borrowed values.
+#[derive(Serialize, Deserialize)]
+struct Model<V> {
+    format: u32,
+    variant: Option<String>,
+    created: Option<String>,
+    config: Option<AgentConfig>,
+    stats: TrainingStats,
+    states_values: V,
+}
+
+/// The models of each format, tried in turn.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum SavedModel {
+    Model(Model<HashMap<String, f32>>),
+    /// Models were saved as the bare map of values before they had a config.
+    Values(HashMap<String, f32>),
+}
+
+/// Just the format of a model, read first so that files from newer versions
+/// fail with a clear error.
+#[derive(Deserialize)]
+struct Version {
+    #[serde(default)]
+    format: u32,
+}
+
+/// Reads the model saved as `name` in `storage` and checks that it was
+/// trained on the game of `board`: what is known about it, its variant being
+/// `board`'s from then on, and its values.
+///
+/// Models saved before variants were recorded are only checked to have
+/// values for positions of the same number of cells.
+pub(crate) fn read_model<S: GameState>(
+    name: &str,
+    board: &S,
+    storage: &Storage,
+) -> Result<(ModelInfo, HashMap<String, f32>), Error> {
+    let filename = storage.path(&format!("policy_{}", name))?;
+
+    let mut file = match File::open(filename) {
+        Ok(f) => f,
+        Err(e) if e.kind() == ErrorKind::NotFound => {
+            return Err(Error::MissingModel(name.to_string()))
+        }
+        Err(e) => return Err(Error::Io(e)),
+    };
+
+    let mut serialized_string = String::new();
+    file.read_to_string(&mut serialized_string)?;
+
+    let incompatible =
+        |reason: String| Error::IncompatibleModel(format!("Agent {} {}", name, reason));
+
+    let version: Version = serde_json::from_str(&serialized_string)?;
+    if version.format > MODEL_FORMAT {
+        return Err(incompatible(format!(
+            "was saved in format {}, which this version cannot read",
+            version.format
+        )));
     }
 
-    pub fn load_model(&mut self) -> Result<(), String> {
-        let filename = format!("data/policy_{}", self.name);
+    let (info, states_values) = match serde_json::from_str(&serialized_string)? {
+        SavedModel::Model(model) => (
+            ModelInfo {
+                variant: model.variant,
+                created: model.created,
+                config: model.config,
+                stats: model.stats,
+            },
+            model.states_values,
+        ),
+        SavedModel::Values(states_values) => (ModelInfo::default(), states_values),
+    };
 
-        let mut file = match File::open(filename) {
-            Ok(f) => f,
-            Err(_) => {
-                return Err(format!(
-                    "Cannot load agent {} file. Are you sure you have trained one first?",
-                    self.name
-                ))
+    let variant = board.variant();
+    match &info.variant {
+        Some(v) if *v != variant => {
+            return Err(incompatible(format!(
+                "was trained on {}, not {}",
+                v, variant
+            )))
+        }
+        Some(_) => {}
+        None => {
+            let cells = board.get_hash().len();
+            if let Some(key) = states_values
+                .keys()
+                .find(|k| k.split(':').next().map_or(0, str::len) != cells)
+            {
+                return Err(incompatible(format!(
+                    "has values for {:?}, which is no position of {}",
+                    key, variant
+                )));
             }
-        };
+        }
+    }
+
+    Ok((
+        ModelInfo {
+            variant: Some(variant),
+            ..info
+        },
+        states_values,
+    ))
+}
+
+/// Saves `states_values` as the model `name` in `storage` along with `info`,
+/// in the current format. `config` is what the agent learned them with, which replaces
+/// the config of earlier runs in `info`.
+pub(crate) fn write_model(
+    name: &str,
+    info: &ModelInfo,
+    config: AgentConfig,
+    states_values: &HashMap<String, f32>,
+    storage: &Storage,
+) -> Result<(), Error> {
+    let filename = storage.path(&format!("policy_{}", name))?;
+    // Sorted so that the same values always make the same file.
+    let serialized_string = serde_json::to_string(&Model {
+        format: MODEL_FORMAT,
+        variant: info.variant.clone(),
+        created: info.created.clone(),
+        config: Some(config),
+        stats: info.stats,
+        states_values: states_values.iter().collect::<BTreeMap<_, _>>(),
+    })?;
 
-        let mut serialized_string = String::new();
+    storage::write_atomic(&filename, serialized_string.as_bytes())?;
+    println!(
+        "Successfully saved {} states to {}",
+        states_values.len(),
+        filename.display()
+    );
 
-        match file.read_to_string(&mut serialized_string) {
-            Ok(_) => {}
-            Err(e) => return Err(e.to_string()),
+    Ok(())
+}
+
+/// What every learning agent keeps besides what it needs to play: its
+/// values and what is saved with them, how far along its training it is and
+/// its random choices. `Learner` works on it for all of them.
+#[derive(Debug, Clone)]
+pub struct LearnerState {
+    pub(crate) name: String,
+    pub(crate) config: AgentConfig,
+    /// What the model says about how the agent was trained, kept when saving
+    /// it again.
+    pub(crate) info: ModelInfo,
+    pub(crate) values: HashMap<String, f32>,
+    /// How much of its training is done, from 0 to 1.
+    pub(crate) progress: f32,
+    /// Whether the agent learns from the games it plays.
+    pub(crate) learning: bool,
+    pub(crate) rng: StdRng,
+}
+
+impl LearnerState {
+    /// The state of an untrained agent called `name`, drawing its random
+    /// choices from a stream of the seed of `config` of its own.
+    pub fn new(name: &str, config: AgentConfig) -> Self {
+        Self {
+            name: name.to_string(),
+            config,
+            info: ModelInfo::default(),
+            values: HashMap::new(),
+            progress: 0.0,
+            learning: true,
+            rng: seed::rng(seed::derive(config.seed, name)),
         }
+    }
 
-        let deserialized: HashMap<String, f32> = match serde_json::from_str(&serialized_string) {
-            Ok(v) => v,
-            Err(e) => return Err(e.to_string()),
-        };
+    /// The value learned for `key`, `initial_value` if there is none yet.
+    pub(crate) fn value(&self, key: &str) -> f32 {
+        self.values
+            .get(key)
+            .copied()
+            .unwrap_or(self.config.initial_value)
+    }
+
+    /// Draws the next random choices from the stream `stream` of the agent.
+    pub(crate) fn reseed(&mut self, stream: &str) {
+        let stream = format!("{}{}", self.name, stream);
+        self.rng = seed::rng(seed::derive(self.config.seed, &stream));
+    }
 
-        self.states_values = deserialized;
+    pub(crate) fn save_model(&self, storage: &Storage) -> Result<(), Error> {
+        write_model(&self.name, &self.info, self.config, &self.values, storage)
+    }
+}
 
-        Ok(())
+/// A player learning the value of the positions after its moves, the
+/// afterstate algorithm of `AgentConfig::algorithm`.
+#[derive(Debug, Clone)]
+pub struct Agent {
+    learner: LearnerState,
+    states: Vec<String>,
+    /// The eligibility trace of each of `states` when learning with TD(λ).
+    traces: Vec<f32>,
+}
+
+impl Agent {
+    pub fn with_config(name: &str, config: AgentConfig) -> Self {
+        Self {
+            learner: LearnerState::new(name, config),
+            states: vec![],
+            traces: vec![],
+        }
     }
 
-    pub fn get_best_action(&self, board: &Board, turn: u8) -> usize {
-        let mut action: usize = 0;
-        let positions = board.get_available();
-        let mut rng = rand::thread_rng();
-        if rng.gen::<f32>() <= self.exp_rate {
-            action = *positions.choose(&mut rng).unwrap();
+    fn key<S: GameState>(&self, board: &S) -> String {
+        if self.learner.config.symmetric {
+            board.get_canonical_hash()
         } else {
-            let mut max_value = f32::MIN;
-            for p in positions.iter() {
-                let mut next_board = board.clone();
-                next_board.board[*p] = turn;
-                let saved_state = self.states_values.get(&next_board.get_hash());
-                let value = if let Some(v) = saved_state { *v } else { 0.0 };
-                if value > max_value {
-                    max_value = value;
-                    action = *p;
-                }
-            }
+            board.get_hash()
         }
+    }
 
-        action
+    pub fn get_best_action<S: GameState>(&mut self, board: &S, turn: u8) -> usize {
+        let moves: Vec<(usize, f32)> = board
+            .get_available()
+            .into_iter()
+            .filter_map(|p| {
+                let mut next_board = board.clone();
+                next_board.play_move(p, turn).ok()?;
+                Some((p, self.learner.value(&self.key(&next_board))))
+            })
+            .collect();
+
+        self.learner
+            .config
+            .exploration
+            .choose(&moves, self.learner.progress, &mut self.learner.rng)
     }
 
     pub fn feed_reward(&mut self, reward: f32) {
         let mut reward = reward;
         for s in self.states.iter().rev() {
-            let entry = self.states_values.entry(s.clone()).or_insert(0.0);
-            *entry += self.lr * (reward - *entry);
-            reward = *entry;
+            let entry = self
+                .learner
+                .values
+                .entry(s.clone())
+                .or_insert(self.learner.config.initial_value);
+            *entry += self.learner.config.learning_rate * (reward - *entry);
+            reward = self.learner.config.discount * *entry;
+        }
+    }
+
+    /// Moves the values of the positions played so far towards `target` for
+    /// the last one, each as much as its eligibility trace.
+    fn td_update(&mut self, target: f32) {
+        let last = match self.states.last() {
+            Some(last) => last,
+            None => return,
+        };
+        let error = target - self.learner.value(last);
+
+        for (s, trace) in self.states.iter().zip(&self.traces) {
+            let entry = self
+                .learner
+                .values
+                .entry(s.clone())
+                .or_insert(self.learner.config.initial_value);
+            *entry += self.learner.config.learning_rate * error * trace;
+        }
+    }
+
+    fn uses_traces(&self) -> bool {
+        self.learner.config.lambda < 1.0
+    }
+
+    /// Learns from the end of the game.
+    fn end_game(&mut self, reward: f32) {
+        if self.uses_traces() {
+            self.td_update(reward);
+        } else {
+            self.feed_reward(reward);
         }
     }
 
     pub fn reset(&mut self) {
         self.states = vec![];
+        self.traces = vec![];
     }
 
-    pub fn add_state(&mut self, board: &Board) {
-        self.states.push(board.get_hash());
+    pub fn add_state<S: GameState>(&mut self, board: &S) {
+        let key = self.key(board);
+
+        if self.uses_traces() {
+            let target = self.learner.config.discount * self.learner.value(&key);
+            self.td_update(target);
+
+            let decay = self.learner.config.discount * self.learner.config.lambda;
+            for trace in &mut self.traces {
+                *trace *= decay;
+            }
+            self.traces.push(1.0);
+        }
+
+        self.states.push(key);
+    }
+}
+
+impl<S: GameState> Learner<S> for Agent {
+    fn learner(&self) -> &LearnerState {
+        &self.learner
+    }
+
+    fn learner_mut(&mut self) -> &mut LearnerState {
+        &mut self.learner
+    }
+
+    fn fork(&self, worker: usize) -> Box<dyn Learner<S>> {
+        let mut copy = self.clone();
+        copy.learner.reseed(&format!("#{}", worker));
+        Box::new(copy)
+    }
+
+    /// The agent learns values of the positions after its moves.
+    fn knows(&self, board: &S) -> bool {
+        self.learner.values.contains_key(&self.key(board))
+    }
+}
+
+impl<S: GameState> Player<S> for Agent {
+    fn choose_move(&mut self, board: &S, symbol: u8) -> usize {
+        self.get_best_action(board, symbol)
+    }
+
+    fn observe_move(&mut self, board: &S, _position: usize, mover: u8, me: u8) {
+        // Without the positions it played, the end of the game updates nothing.
+        if mover == me && self.learner.learning {
+            self.add_state(board);
+        }
+    }
+
+    fn observe_undo(&mut self, _board: &S, _position: usize, mover: u8, me: u8) {
+        if mover == me {
+            self.states.pop();
+            self.traces.pop();
+        }
+    }
+
+    fn observe_end(&mut self, _board: &S, winner: Option<u8>, me: u8) {
+        if let Some(reward) = self.learner.config.reward(winner, me) {
+            self.end_game(reward);
+        }
+
+        self.reset();
     }
 }
@@ -0,0 +1,107 @@
+use crate::game::DIRECTIONS;
+
+/// The cells held by each player as bit masks, bit `i` standing for cell `i`.
+///
+/// Only boards of up to 16 cells fit, which covers 3x3 and 4x4.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct BitBoard {
+    pub x: u16,
+    pub o: u16,
+}
+
+/// The eight winning lines of the classic 3x3 board.
+pub const WIN_MASKS_3X3: [u16; 8] = [
+    0b000_000_111,
+    0b000_111_000,
+    0b111_000_000,
+    0b001_001_001,
+    0b010_010_010,
+    0b100_100_100,
+    0b100_010_001,
+    0b001_010_100,
+];
+
+/// Largest number of cells a `BitBoard` can hold.
+pub const MAX_CELLS: usize = 16;
+
+impl BitBoard {
+    pub fn set(&mut self, position: usize, symbol: u8) {
+        let bit = 1 << position;
+        self.x &= !bit;
+        self.o &= !bit;
+        match symbol {
+            b'X' => self.x |= bit,
+            b'O' => self.o |= bit,
+            _ => {}
+        }
+    }
+
+    /// Mask of the empty cells among the first `cells` ones.
+    pub fn empty(&self, cells: usize) -> u16 {
+        !(self.x | self.o) & full_mask(cells)
+    }
+
+    /// The player owning one of `masks` completely, if any.
+    pub fn winner(&self, masks: &[u16]) -> Option<u8> {
+        for &m in masks {
+            if self.x & m == m {
+                return Some(b'X');
+            }
+            if self.o & m == m {
+                return Some(b'O');
+            }
+        }
+        None
+    }
+
+    pub fn get_turn(&self) -> u8 {
+        if self.x.count_ones() > self.o.count_ones() {
+            b'O'
+        } else {
+            b'X'
+        }
+    }
+}
+
+pub fn full_mask(cells: usize) -> u16 {
+    if cells >= MAX_CELLS {
+        u16::MAX
+    } else {
+        (1 << cells) - 1
+    }
+}
+
+/// Computes the masks of every line of `win_length` cells on a board of at
+/// most `MAX_CELLS` cells.
+pub fn win_masks(width: usize, height: usize, win_length: usize) -> Vec<u16> {
+    if (width, height, win_length) == (3, 3, 3) {
+        return WIN_MASKS_3X3.to_vec();
+    }
+
+    let mut masks = vec![];
+
+    for row in 0..height as isize {
+        for col in 0..width as isize {
+            for (d_row, d_col) in DIRECTIONS {
+                let cells: Vec<(isize, isize)> = (0..win_length as isize)
+                    .map(|step| (row + d_row * step, col + d_col * step))
+                    .collect();
+
+                if cells
+                    .iter()
+                    .all(|&(r, c)| r >= 0 && c >= 0 && r < height as isize && c < width as isize)
+                {
+                    masks.push(
+                        cells
+                            .iter()
+                            .fold(0, |m, &(r, c)| m | 1 << (r as usize * width + c as usize)),
+                    );
+                }
+            }
+        }
+    }
+
+    masks.sort_unstable();
+    masks.dedup();
+    masks
+}
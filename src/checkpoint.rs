@@ -0,0 +1,94 @@
+use crate::agent::{AgentConfig, ModelInfo};
+use crate::error::Error;
+use crate::storage::{self, Storage};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+
+/// How a training run keeps its progress.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CheckpointConfig {
+    /// Saves the model and a checkpoint every this many cycles, `None` only
+    /// saving the model once training is over.
+    pub every: Option<usize>,
+    /// Continues the agent's checkpoint if it has one, or else trains its
+    /// saved model further instead of starting from scratch. A checkpoint is
+    /// continued with its own interval rather than `every`.
+    pub resume: bool,
+}
+
+impl CheckpointConfig {
+    /// Whether a checkpoint is due after playing the cycles from `before` to
+    /// `after` out of `cycles`. None is saved once all of them are played.
+    pub(crate) fn due(&self, before: usize, after: usize, cycles: usize) -> bool {
+        match self.every {
+            Some(every) if every > 0 => after < cycles && before / every != after / every,
+            _ => false,
+        }
+    }
+}
+
+/// A training run saved part way through in `checkpoint_{name}` in the models
+/// directory, and removed once the run is over.
+///
+/// The exploration schedule follows from the cycles played, and the agents
+/// draw their random choices from a stream derived from the cycle of the
+/// checkpoint, so with a seed a resumed run plays the same games as one that
+/// was never interrupted.
+#[derive(Serialize, Deserialize)]
+pub(crate) struct Checkpoint<V = HashMap<String, f32>> {
+    /// The cycles played so far.
+    pub cycle: usize,
+    /// The cycles of the whole run.
+    pub cycles: usize,
+    /// The cycles between two checkpoints, which a resumed run keeps so that
+    /// the agents draw from the same streams.
+    pub every: usize,
+    pub config: AgentConfig,
+    /// What the model of the agent recorded before the run.
+    pub info: ModelInfo,
+    /// The values of the agent being trained.
+    pub agent: V,
+    /// The values of its self-play partner, `None` when training against
+    /// minimax.
+    pub partner: Option<V>,
+}
+
+impl Checkpoint {
+    /// Reads the checkpoint of the agent called `name`, `None` if it has none.
+    pub fn load(name: &str, storage: &Storage) -> Result<Option<Self>, Error> {
+        match fs::read_to_string(filename(name, storage)?) {
+            Ok(s) => Ok(Some(serde_json::from_str(&s)?)),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(Error::Io(e)),
+        }
+    }
+
+    /// Removes the checkpoint of the agent called `name`, if any.
+    pub fn remove(name: &str, storage: &Storage) -> Result<(), Error> {
+        match fs::remove_file(filename(name, storage)?) {
+            Err(e) if e.kind() != ErrorKind::NotFound => Err(Error::Io(e)),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl Checkpoint<&HashMap<String, f32>> {
+    pub fn save(&self, name: &str, storage: &Storage) -> Result<(), Error> {
+        let filename = filename(name, storage)?;
+        storage::write_atomic(&filename, serde_json::to_string(self)?.as_bytes())?;
+        println!(
+            "Saved a checkpoint at cycle {} to {}",
+            self.cycle,
+            filename.display()
+        );
+
+        Ok(())
+    }
+}
+
+fn filename(name: &str, storage: &Storage) -> Result<PathBuf, Error> {
+    storage.path(&format!("checkpoint_{}", name))
+}
@@ -0,0 +1,84 @@
+use crate::game::BoardError;
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+
+/// Everything that can go wrong in the library.
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    Serialization(serde_json::Error),
+    /// No saved model exists for the agent with this name.
+    MissingModel(String),
+    /// A saved model cannot be used for the game being played.
+    IncompatibleModel(String),
+    /// The directory models are kept in does not exist.
+    MissingModelsDir(PathBuf),
+    /// A player tried a move the rules do not allow.
+    IllegalMove(BoardError),
+    /// A game was started on a board where it is already over.
+    GameOver,
+    /// A game record could not be read or does not describe a legal game.
+    InvalidRecord(String),
+    /// A tablebase cannot be built for the board or its file is damaged.
+    Tablebase(String),
+    /// The game has too many positions to go through all of them.
+    TooLarge(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "{}", e),
+            Error::Serialization(e) => write!(f, "{}", e),
+            Error::MissingModel(name) => write!(
+                f,
+                "Cannot load agent {} file. Are you sure you have trained one first?",
+                name
+            ),
+            Error::IncompatibleModel(e) => write!(f, "{}", e),
+            Error::MissingModelsDir(dir) => write!(
+                f,
+                "The models directory {} does not exist, create it or pick another one",
+                dir.display()
+            ),
+            Error::IllegalMove(e) => write!(f, "{}", e),
+            Error::GameOver => write!(f, "The game is already over"),
+            Error::InvalidRecord(e) => write!(f, "Invalid game record: {}", e),
+            Error::Tablebase(e) => write!(f, "{}", e),
+            Error::TooLarge(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(e) => Some(e),
+            Error::Serialization(e) => Some(e),
+            Error::IllegalMove(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<BoardError> for Error {
+    fn from(e: BoardError) -> Self {
+        match e {
+            BoardError::GameOver => Error::GameOver,
+            e => Error::IllegalMove(e),
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(e: serde_json::Error) -> Self {
+        Error::Serialization(e)
+    }
+}
@@ -0,0 +1,126 @@
+use rand::distributions::WeightedIndex;
+use rand::{prelude::*, seq::SliceRandom};
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+/// How a learning player picks between its best move and the others.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Strategy {
+    /// Always plays the best move, for evaluating what was learned.
+    Greedy,
+    /// Plays a random move with a probability given by the schedule.
+    EpsilonGreedy,
+    /// Picks moves with probabilities growing with their value, the schedule
+    /// giving the temperature: high values play almost at random, low ones
+    /// almost greedily.
+    Softmax,
+}
+
+/// How the schedule goes from its start value to its end value over training.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Decay {
+    Linear,
+    /// Shrinks by the same factor every cycle, staying linear if either end
+    /// is not positive.
+    Exponential,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Exploration {
+    pub strategy: Strategy,
+    pub decay: Decay,
+    /// The epsilon or temperature at the start of training.
+    pub start: f32,
+    /// The epsilon or temperature at the end of training, the same as `start`
+    /// to keep it constant.
+    pub end: f32,
+}
+
+impl Default for Exploration {
+    fn default() -> Self {
+        Self {
+            strategy: Strategy::EpsilonGreedy,
+            decay: Decay::Linear,
+            start: 0.1,
+            end: 0.1,
+        }
+    }
+}
+
+impl Exploration {
+    /// The epsilon or temperature once `progress`, from 0 to 1, of the
+    /// training is done.
+    pub fn value(&self, progress: f32) -> f32 {
+        let progress = progress.clamp(0.0, 1.0);
+
+        match self.decay {
+            Decay::Exponential if self.start > 0.0 && self.end > 0.0 => {
+                self.start * (self.end / self.start).powf(progress)
+            }
+            _ => self.start + (self.end - self.start) * progress,
+        }
+    }
+
+    /// Picks one of `moves`, given with their values, once `progress` of the
+    /// training is done. The first of the best moves is the greedy choice.
+    pub fn choose(&self, moves: &[(usize, f32)], progress: f32, rng: &mut impl Rng) -> usize {
+        let mut best = 0;
+        let mut max_value = f32::MIN;
+        for &(position, value) in moves {
+            if value > max_value {
+                max_value = value;
+                best = position;
+            }
+        }
+
+        match self.strategy {
+            Strategy::Greedy => best,
+            Strategy::EpsilonGreedy => {
+                if rng.gen::<f32>() <= self.value(progress) {
+                    moves.choose(rng).map_or(best, |&(position, _)| position)
+                } else {
+                    best
+                }
+            }
+            Strategy::Softmax => {
+                let temperature = self.value(progress).max(f32::EPSILON);
+                let weights = moves
+                    .iter()
+                    .map(|&(_, v)| ((v - max_value) / temperature).exp());
+
+                match WeightedIndex::new(weights) {
+                    Ok(index) => moves[index.sample(rng)].0,
+                    Err(_) => best,
+                }
+            }
+        }
+    }
+}
+
+impl FromStr for Strategy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "greedy" => Ok(Strategy::Greedy),
+            "epsilon" => Ok(Strategy::EpsilonGreedy),
+            "softmax" => Ok(Strategy::Softmax),
+            _ => Err(format!("Unknown exploration strategy {}", s)),
+        }
+    }
+}
+
+impl FromStr for Decay {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "linear" => Ok(Decay::Linear),
+            "exponential" => Ok(Decay::Exponential),
+            _ => Err(format!("Unknown decay {}", s)),
+        }
+    }
+}
@@ -1,17 +1,226 @@
+use crate::bitboard::{self, BitBoard};
+use crate::states;
 use std::fmt;
 use std::fmt::Formatter;
+use std::str::FromStr;
+use std::sync::Arc;
+
+/// The four directions a line can run in: right, down and both diagonals.
+pub(crate) const DIRECTIONS: [(isize, isize); 4] = [(0, 1), (1, 0), (1, 1), (1, -1)];
+
+/// Maps a row and column to the cell it is taken from, given the width and
+/// height of the board.
+type Transform = fn(usize, usize, usize, usize) -> (usize, usize);
+
+/// The rules of a game as seen by players and by the driver in `play_game`.
+///
+/// Positions are plain indices, which every implementor maps to its own cells.
+pub trait GameState: Clone + fmt::Display {
+    /// Number of positions, playable or not, on the board.
+    fn cells(&self) -> usize;
+
+    /// Number of columns, positions being numbered row by row.
+    fn width(&self) -> usize;
+
+    /// Which game this is, as written in game records: `ultimate` or the
+    /// size of the board as `WIDTHxHEIGHTxWIN_LENGTH`.
+    fn variant(&self) -> String;
+
+    fn get_available(&self) -> Vec<usize>;
+
+    fn play_move(&mut self, position: usize, player: u8) -> Result<(), BoardError>;
+
+    fn get_winner(&self) -> (bool, Option<u8>);
+
+    fn get_turn(&self) -> u8;
+
+    fn get_hash(&self) -> String;
+
+    /// A compact index of the position, `None` if it does not fit in a `u32`.
+    fn get_index(&self) -> Option<u32> {
+        None
+    }
+
+    /// A hash shared by all positions equivalent to this one under the
+    /// symmetries of the board. Defaults to the plain hash.
+    fn get_canonical_hash(&self) -> String {
+        self.get_hash()
+    }
+
+    /// Heuristic score of an unfinished position for `symbol`, used when a
+    /// search is cut off before the end of the game. Always within `-9..=9`.
+    fn evaluate(&self, _symbol: u8) -> i32 {
+        0
+    }
+
+    /// The position as an m,n,k board, `None` for other games. Lets players
+    /// generic over the game use what only exists for boards, like
+    /// tablebases.
+    fn as_board(&self) -> Option<&Board> {
+        None
+    }
+}
+
+/// Largest number of cells `Board::get_index` can encode, as 3^20 still fits
+/// in a `u32`.
+const MAX_INDEX_CELLS: usize = 20;
 
 #[derive(Clone)]
 pub struct Board {
-    pub board: [u8; 9],
+    board: Vec<u8>,
+    /// The same cells as `board`, kept in sync for boards small enough to fit
+    /// a `BitBoard`, along with the masks of their winning lines.
+    bits: Option<BitBoard>,
+    win_masks: Arc<[u16]>,
+    width: usize,
+    height: usize,
+    win_length: usize,
 }
 
 impl Board {
+    /// Creates the classic 3x3 board needing three in a row.
     pub fn new() -> Self {
-        Self { board: [b' '; 9] }
+        Self {
+            board: vec![b' '; 9],
+            bits: Some(BitBoard::default()),
+            win_masks: Arc::from(bitboard::WIN_MASKS_3X3.as_slice()),
+            width: 3,
+            height: 3,
+            win_length: 3,
+        }
+    }
+
+    /// Creates an empty `width` x `height` board where `win_length` symbols in
+    /// a row, column or diagonal win the game.
+    pub fn with_size(width: usize, height: usize, win_length: usize) -> Result<Self, BoardError> {
+        if width == 0 || height == 0 {
+            return Err(BoardError::SizeError(format!(
+                "A board of {}x{} has no cells",
+                width, height
+            )));
+        }
+
+        if win_length == 0 || win_length > width.max(height) {
+            return Err(BoardError::SizeError(format!(
+                "Cannot get {} in a row on a {}x{} board",
+                win_length, width, height
+            )));
+        }
+
+        let cells = width * height;
+        let (bits, win_masks) = if cells <= bitboard::MAX_CELLS {
+            let masks = bitboard::win_masks(width, height, win_length);
+            (Some(BitBoard::default()), Arc::from(masks))
+        } else {
+            (None, Arc::from([]))
+        };
+
+        Ok(Self {
+            board: vec![b' '; cells],
+            bits,
+            win_masks,
+            width,
+            height,
+            win_length,
+        })
+    }
+
+    /// Sets up a `width` x `height` board from its cells written row by row,
+    /// as `get_hash` writes them: `X`, `O`, and a space or `.` for an empty
+    /// cell.
+    ///
+    /// Fails unless the position can be reached in a game, see
+    /// `validate_position`.
+    pub fn from_cells(
+        width: usize,
+        height: usize,
+        win_length: usize,
+        cells: &str,
+    ) -> Result<Self, BoardError> {
+        let mut board = Self::with_size(width, height, win_length)?;
+
+        if cells.chars().count() != board.board.len() {
+            return Err(BoardError::InvalidPosition(format!(
+                "A {}x{} board has {} cells, not {}",
+                width,
+                height,
+                board.board.len(),
+                cells.chars().count()
+            )));
+        }
+
+        for (position, c) in cells.chars().enumerate() {
+            match c {
+                'X' | 'x' => board.set(position, b'X'),
+                'O' | 'o' => board.set(position, b'O'),
+                ' ' | '.' => {}
+                c => {
+                    return Err(BoardError::InvalidPosition(format!(
+                        "Unknown symbol {:?}",
+                        c
+                    )))
+                }
+            }
+        }
+
+        states::validate_position(&board)?;
+        Ok(board)
+    }
+
+    /// Writes the board the way `from_str` reads it, rows separated by `/`.
+    pub fn to_notation(&self) -> String {
+        self.board
+            .chunks(self.width)
+            .map(|row| {
+                row.iter()
+                    .map(|&c| if c == b' ' { '.' } else { c as char })
+                    .collect::<String>()
+            })
+            .collect::<Vec<_>>()
+            .join("/")
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn win_length(&self) -> usize {
+        self.win_length
+    }
+
+    /// The symbol in `position`, `b' '` when it is empty.
+    pub fn get(&self, position: usize) -> u8 {
+        self.board[position]
+    }
+
+    /// All the cells of the board, row by row.
+    pub fn symbols(&self) -> &[u8] {
+        &self.board
+    }
+
+    /// Puts `symbol` in `position` without checking whether the move is legal.
+    pub(crate) fn set(&mut self, position: usize, symbol: u8) {
+        self.board[position] = symbol;
+        if let Some(bits) = &mut self.bits {
+            bits.set(position, symbol);
+        }
     }
 
     pub fn get_available(&self) -> Vec<usize> {
+        if let Some(bits) = self.bits {
+            let mut empty = bits.empty(self.board.len());
+            let mut available = Vec::with_capacity(empty.count_ones() as usize);
+            while empty != 0 {
+                available.push(empty.trailing_zeros() as usize);
+                empty &= empty - 1;
+            }
+            return available;
+        }
+
         self.board
             .iter()
             .enumerate()
@@ -20,44 +229,62 @@ impl Board {
             .collect()
     }
 
+    /// Plays `player` in `position`, checking that the cell exists and is
+    /// empty, that the game is still going, and that it is `player`'s turn.
     pub fn play_move(&mut self, position: usize, player: u8) -> Result<(), BoardError> {
+        if player != b'X' && player != b'O' {
+            return Err(BoardError::InvalidSymbol(player));
+        }
+
+        if position >= self.board.len() {
+            return Err(BoardError::OutOfRange(position));
+        }
+
+        if self.get_winner().0 {
+            return Err(BoardError::GameOver);
+        }
+
+        let turn = self.get_turn();
+        if player != turn {
+            return Err(BoardError::WrongTurn {
+                expected: turn,
+                found: player,
+            });
+        }
+
         if self.board[position] != b' ' {
-            return Err(BoardError::PositionError(format!(
-                "Position {} is occupied by {}",
-                position, self.board[position]
-            )));
+            return Err(BoardError::Occupied {
+                position,
+                by: self.board[position],
+            });
         }
 
-        self.board[position] = player;
+        self.set(position, player);
 
         Ok(())
     }
 
     pub fn get_winner(&self) -> (bool, Option<u8>) {
-        for i in 0..3 {
-            if self.board[i] == self.board[i + 3]
-                && self.board[i] == self.board[i + 6]
-                && self.board[i] != b' '
-            {
-                return (true, Some(self.board[i]));
-            }
-
-            if self.board[i * 3] == self.board[i * 3 + 1]
-                && self.board[i * 3] == self.board[i * 3 + 2]
-                && self.board[i * 3] != b' '
-            {
-                return (true, Some(self.board[i * 3]));
-            }
+        if let Some(bits) = self.bits {
+            return match bits.winner(&self.win_masks) {
+                Some(winner) => (true, Some(winner)),
+                None => (bits.empty(self.board.len()) == 0, None),
+            };
         }
 
-        if self.board[0] == self.board[4] && self.board[4] == self.board[8] && self.board[0] != b' '
-        {
-            return (true, Some(self.board[0]));
-        }
+        for row in 0..self.height {
+            for col in 0..self.width {
+                let symbol = self.board[row * self.width + col];
+                if symbol == b' ' {
+                    continue;
+                }
 
-        if self.board[2] == self.board[4] && self.board[4] == self.board[6] && self.board[2] != b' '
-        {
-            return (true, Some(self.board[2]));
+                for (d_row, d_col) in DIRECTIONS {
+                    if self.is_line(row, col, d_row, d_col, symbol) {
+                        return (true, Some(symbol));
+                    }
+                }
+            }
         }
 
         if self.count_symbol(b' ') == 0 {
@@ -67,12 +294,95 @@ impl Board {
         (false, None)
     }
 
+    /// Whether `symbol` holds `win_length` cells in a row anywhere.
+    pub(crate) fn has_line(&self, symbol: u8) -> bool {
+        (0..self.height).any(|row| {
+            (0..self.width).any(|col| {
+                DIRECTIONS
+                    .iter()
+                    .any(|&(d_row, d_col)| self.is_line(row, col, d_row, d_col, symbol))
+            })
+        })
+    }
+
+    /// Whether `win_length` cells starting at `row`, `col` and moving by
+    /// `d_row`, `d_col` all hold `symbol`.
+    fn is_line(&self, row: usize, col: usize, d_row: isize, d_col: isize, symbol: u8) -> bool {
+        (0..self.win_length as isize).all(|step| {
+            let r = row as isize + d_row * step;
+            let c = col as isize + d_col * step;
+
+            r >= 0
+                && c >= 0
+                && (r as usize) < self.height
+                && (c as usize) < self.width
+                && self.board[r as usize * self.width + c as usize] == symbol
+        })
+    }
+
+    /// Scores the lines still open for each player: every line holding only
+    /// `symbol`s counts for how many it holds, every line holding only the
+    /// opponent's counts against. Clamped to `-9..=9`.
+    pub fn evaluate(&self, symbol: u8) -> i32 {
+        let mut score = 0;
+
+        for row in 0..self.height {
+            for col in 0..self.width {
+                for (d_row, d_col) in DIRECTIONS {
+                    if let Some((mine, theirs)) = self.count_line(row, col, d_row, d_col, symbol) {
+                        if theirs == 0 {
+                            score += mine;
+                        } else if mine == 0 {
+                            score -= theirs;
+                        }
+                    }
+                }
+            }
+        }
+
+        score.clamp(-9, 9)
+    }
+
+    /// Counts the cells held by `symbol` and by the opponent on the line of
+    /// `win_length` cells starting at `row`, `col` and moving by `d_row`,
+    /// `d_col`. `None` if the line does not fit on the board.
+    fn count_line(
+        &self,
+        row: usize,
+        col: usize,
+        d_row: isize,
+        d_col: isize,
+        symbol: u8,
+    ) -> Option<(i32, i32)> {
+        let (mut mine, mut theirs) = (0, 0);
+
+        for step in 0..self.win_length as isize {
+            let r = row as isize + d_row * step;
+            let c = col as isize + d_col * step;
+
+            if r < 0 || c < 0 || r as usize >= self.height || c as usize >= self.width {
+                return None;
+            }
+
+            match self.board[r as usize * self.width + c as usize] {
+                b' ' => {}
+                s if s == symbol => mine += 1,
+                _ => theirs += 1,
+            }
+        }
+
+        Some((mine, theirs))
+    }
+
     pub fn get_turn(&self) -> u8 {
-        let free = self.count_symbol(b' ');
-        if free % 2 > 0 {
-            b'X'
-        } else {
+        if let Some(bits) = self.bits {
+            return bits.get_turn();
+        }
+
+        if self.count_symbol(b'X') > self.count_symbol(b'O') {
             b'O'
+        } else {
+            b'X'
         }
     }
 
@@ -83,15 +393,172 @@ impl Board {
     pub fn get_hash(&self) -> String {
         self.board.iter().map(|c| *c as char).collect()
     }
+
+    /// The position read as a base 3 number, empty cells counting 0, X 1 and
+    /// O 2, with the first cell as the least significant digit. Only boards of
+    /// up to 20 cells have one.
+    pub fn get_index(&self) -> Option<u32> {
+        if self.board.len() > MAX_INDEX_CELLS {
+            return None;
+        }
+
+        Some(self.board.iter().rev().fold(0, |index, &c| {
+            index * 3
+                + match c {
+                    b'X' => 1,
+                    b'O' => 2,
+                    _ => 0,
+                }
+        }))
+    }
+
+    /// The smallest hash among all rotations and reflections of the board,
+    /// so that equivalent positions share it.
+    pub fn get_canonical_hash(&self) -> String {
+        let (w, h) = (self.width, self.height);
+
+        let mut transforms: Vec<Transform> = vec![
+            |r, c, _, _| (r, c),
+            |r, c, w, h| (h - 1 - r, w - 1 - c),
+            |r, c, w, _| (r, w - 1 - c),
+            |r, c, _, h| (h - 1 - r, c),
+        ];
+
+        // Rotating by a quarter turn or mirroring along a diagonal only keeps
+        // the shape of square boards.
+        if w == h {
+            transforms.extend_from_slice(&[
+                |r, c, w, _| (c, w - 1 - r),
+                |r, c, w, _| (w - 1 - c, r),
+                |r, c, _, _| (c, r),
+                |r, c, w, _| (w - 1 - c, w - 1 - r),
+            ]);
+        }
+
+        transforms
+            .into_iter()
+            .map(|t| {
+                (0..h)
+                    .flat_map(|r| (0..w).map(move |c| t(r, c, w, h)))
+                    .map(|(r, c)| self.board[r * w + c] as char)
+                    .collect::<String>()
+            })
+            .min()
+            .unwrap()
+    }
+}
+
+impl GameState for Board {
+    fn cells(&self) -> usize {
+        self.board.len()
+    }
+
+    fn width(&self) -> usize {
+        self.width
+    }
+
+    fn variant(&self) -> String {
+        format!("{}x{}x{}", self.width, self.height, self.win_length)
+    }
+
+    fn get_available(&self) -> Vec<usize> {
+        Board::get_available(self)
+    }
+
+    fn play_move(&mut self, position: usize, player: u8) -> Result<(), BoardError> {
+        Board::play_move(self, position, player)
+    }
+
+    fn get_winner(&self) -> (bool, Option<u8>) {
+        Board::get_winner(self)
+    }
+
+    fn get_turn(&self) -> u8 {
+        Board::get_turn(self)
+    }
+
+    fn get_hash(&self) -> String {
+        Board::get_hash(self)
+    }
+
+    fn get_canonical_hash(&self) -> String {
+        Board::get_canonical_hash(self)
+    }
+
+    fn get_index(&self) -> Option<u32> {
+        Board::get_index(self)
+    }
+
+    fn evaluate(&self, symbol: u8) -> i32 {
+        Board::evaluate(self, symbol)
+    }
+
+    fn as_board(&self) -> Option<&Board> {
+        Some(self)
+    }
+}
+
+/// Reads a board either as `get_hash` writes it, which must then be square, or
+/// row by row with `X`, `O` and `.` and rows separated by `/`, as in
+/// `XO./.X./..O`. Either can be followed by the number of symbols in a row
+/// needed to win, which defaults to the shorter side of the board.
+///
+/// See `Board::from_cells` for the positions that are accepted.
+impl FromStr for Board {
+    type Err = BoardError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim_matches(|c| c == '\n' || c == '\r');
+        let (cells, win_length) = match s.rsplit_once(' ') {
+            Some((cells, k)) if !k.is_empty() && k.bytes().all(|b| b.is_ascii_digit()) => {
+                (cells, k.parse().ok())
+            }
+            _ => (s, None),
+        };
+
+        let (width, height, cells) = if cells.contains('/') {
+            let rows: Vec<&str> = cells.trim().split('/').collect();
+            let width = rows[0].chars().count();
+            if rows.iter().any(|row| row.chars().count() != width) {
+                return Err(BoardError::InvalidPosition(
+                    "All rows must have the same length".to_string(),
+                ));
+            }
+            (width, rows.len(), rows.concat())
+        } else {
+            let len = cells.chars().count();
+            let side = (1..=len).find(|side| side * side >= len).unwrap_or(0);
+            if side * side != len {
+                return Err(BoardError::InvalidPosition(format!(
+                    "{} cells do not make a square board, separate the rows with /",
+                    len
+                )));
+            }
+            (side, side, cells.to_string())
+        };
+
+        Board::from_cells(
+            width,
+            height,
+            win_length.unwrap_or_else(|| width.min(height)),
+            &cells,
+        )
+    }
+}
+
+impl Default for Board {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl fmt::Display for Board {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
-        for i in 0..9 {
-            if i % 3 == 0 {
+        for i in 0..self.board.len() {
+            if i % self.width == 0 {
                 writeln!(f)?;
                 if i != 0 {
-                    writeln!(f, "-----------")?;
+                    writeln!(f, "{}", "-".repeat(self.width * 4 - 1))?;
                 }
             } else {
                 write!(f, "|")?;
@@ -102,15 +569,176 @@ impl fmt::Display for Board {
     }
 }
 
+#[derive(Debug, PartialEq, Eq)]
 pub enum BoardError {
-    PositionError(String),
+    /// The position is past the last cell of the board.
+    OutOfRange(usize),
+    Occupied {
+        position: usize,
+        by: u8,
+    },
+    /// The board already has a winner or is full.
+    GameOver,
+    WrongTurn {
+        expected: u8,
+        found: u8,
+    },
+    /// Only `b'X'` and `b'O'` can be played.
+    InvalidSymbol(u8),
+    /// In ultimate tic-tac-toe, the move has to be played in another
+    /// sub-board.
+    WrongBoard {
+        expected: usize,
+        found: usize,
+    },
+    /// In ultimate tic-tac-toe, the sub-board is already won or full.
+    ClosedBoard(usize),
+    SizeError(String),
+    /// A board read from text does not describe a reachable position.
+    InvalidPosition(String),
 }
 
-impl BoardError {
-    pub fn value(&self) -> String {
+impl fmt::Display for BoardError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         match self {
-            BoardError::PositionError(s) => s.clone(),
+            BoardError::OutOfRange(position) => {
+                write!(f, "Position {} is outside the board", position + 1)
+            }
+            BoardError::Occupied { position, by } => write!(
+                f,
+                "Position {} is occupied by {}",
+                position + 1,
+                *by as char
+            ),
+            BoardError::GameOver => write!(f, "The game is already over"),
+            BoardError::WrongTurn { expected, found } => write!(
+                f,
+                "It is {}'s turn, not {}'s",
+                *expected as char, *found as char
+            ),
+            BoardError::InvalidSymbol(symbol) => {
+                write!(f, "Cannot play {:?}, only X and O", *symbol as char)
+            }
+            BoardError::WrongBoard { expected, found } => write!(
+                f,
+                "The move has to be played in board {}, not {}",
+                expected + 1,
+                found + 1
+            ),
+            BoardError::ClosedBoard(board) => write!(f, "Board {} is already decided", board + 1),
+            BoardError::SizeError(s) => write!(f, "{}", s),
+            BoardError::InvalidPosition(s) => write!(f, "Invalid position: {}", s),
         }
     }
 }
 
+impl std::error::Error for BoardError {}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// Plays a move that must be rejected with `error`, checking that the
+    /// board is left as it was.
+    pub(crate) fn assert_rejected<S: GameState>(
+        board: &mut S,
+        position: usize,
+        player: u8,
+        error: BoardError,
+    ) {
+        let before = board.get_hash();
+        assert_eq!(board.play_move(position, player), Err(error));
+        assert_eq!(board.get_hash(), before);
+    }
+
+    #[test]
+    fn parses_notation_back() {
+        let mut wide = Board::with_size(5, 3, 3).unwrap();
+        wide.play_move(0, b'X').unwrap();
+        wide.play_move(7, b'O').unwrap();
+        let written = format!("{} {}", wide.to_notation(), wide.win_length());
+        let parsed: Board = written.parse().unwrap();
+        assert_eq!(parsed.to_notation(), wide.to_notation());
+        assert_eq!(parsed.win_length(), 3);
+
+        for position in states::enumerate_positions(&Board::new()) {
+            let parsed: Board = position.board.to_notation().parse().unwrap();
+            assert_eq!(parsed.get_hash(), position.board.get_hash());
+        }
+    }
+
+    #[test]
+    fn parses_hash_back() {
+        for position in states::enumerate_positions(&Board::new()) {
+            let parsed: Board = position.board.get_hash().parse().unwrap();
+            assert_eq!(parsed.get_hash(), position.board.get_hash());
+        }
+    }
+
+    #[test]
+    fn reads_trailing_win_length() {
+        let board: Board = "..../..../..../.... 3".parse().unwrap();
+        assert_eq!((board.width(), board.height()), (4, 4));
+        assert_eq!(board.win_length(), 3);
+    }
+
+    #[test]
+    fn rejects_uneven_rows() {
+        assert_eq!(
+            "XO./O./...".parse::<Board>().err(),
+            Some(BoardError::InvalidPosition(
+                "All rows must have the same length".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_symbol() {
+        assert_rejected(&mut Board::new(), 0, b'Z', BoardError::InvalidSymbol(b'Z'));
+    }
+
+    #[test]
+    fn rejects_out_of_range() {
+        assert_rejected(&mut Board::new(), 9, b'X', BoardError::OutOfRange(9));
+    }
+
+    #[test]
+    fn rejects_move_on_won_board() {
+        let mut board: Board = "XXX/OO./...".parse().unwrap();
+        assert_rejected(&mut board, 8, b'O', BoardError::GameOver);
+    }
+
+    #[test]
+    fn rejects_move_on_full_board() {
+        let mut board: Board = "XOX/XOO/OXX".parse().unwrap();
+        assert_rejected(&mut board, 0, b'O', BoardError::GameOver);
+    }
+
+    #[test]
+    fn rejects_wrong_turn() {
+        assert_rejected(
+            &mut Board::new(),
+            0,
+            b'O',
+            BoardError::WrongTurn {
+                expected: b'X',
+                found: b'O',
+            },
+        );
+    }
+
+    #[test]
+    fn rejects_occupied_cell() {
+        let mut board = Board::new();
+        board.play_move(4, b'X').unwrap();
+        assert_rejected(
+            &mut board,
+            4,
+            b'O',
+            BoardError::Occupied {
+                position: 4,
+                by: b'X',
+            },
+        );
+    }
+}
@@ -0,0 +1,120 @@
+use crate::game::{Board, BoardError, GameState};
+
+/// A board together with the moves that led to it, which can be taken back
+/// and played again.
+///
+/// Undone moves are kept until a different move is played, so they can be
+/// redone.
+#[derive(Clone)]
+pub struct Game<S: GameState = Board> {
+    start: S,
+    board: S,
+    moves: Vec<(usize, u8)>,
+    /// How many of `moves` are on the board.
+    ply: usize,
+}
+
+impl<S: GameState> Game<S> {
+    pub fn new(start: S) -> Self {
+        Self {
+            board: start.clone(),
+            start,
+            moves: vec![],
+            ply: 0,
+        }
+    }
+
+    pub fn board(&self) -> &S {
+        &self.board
+    }
+
+    /// The board the game started from.
+    pub fn start(&self) -> &S {
+        &self.start
+    }
+
+    /// The moves on the board, oldest first, with the symbol that played them.
+    pub fn moves(&self) -> &[(usize, u8)] {
+        &self.moves[..self.ply]
+    }
+
+    pub fn ply(&self) -> usize {
+        self.ply
+    }
+
+    /// Plays a move. The moves undone before it are forgotten, unless it is
+    /// the first of them, which is then redone.
+    pub fn play(&mut self, position: usize, player: u8) -> Result<(), BoardError> {
+        self.board.play_move(position, player)?;
+        if self.moves.get(self.ply) != Some(&(position, player)) {
+            self.moves.truncate(self.ply);
+            self.moves.push((position, player));
+        }
+        self.ply += 1;
+        Ok(())
+    }
+
+    /// Takes back the last move and returns it.
+    pub fn undo(&mut self) -> Option<(usize, u8)> {
+        if self.ply == 0 {
+            return None;
+        }
+
+        self.ply -= 1;
+        self.board = self.rebuild();
+        Some(self.moves[self.ply])
+    }
+
+    /// Plays the last undone move again and returns it.
+    pub fn redo(&mut self) -> Option<(usize, u8)> {
+        let (position, player) = *self.moves.get(self.ply)?;
+        self.board.play_move(position, player).ok()?;
+        self.ply += 1;
+        Some((position, player))
+    }
+
+    /// Undoes or redoes moves until `ply` moves are on the board. Asking for
+    /// more moves than were ever played stops at the last one.
+    pub fn replay_to(&mut self, ply: usize) {
+        self.ply = ply.min(self.moves.len());
+        self.board = self.rebuild();
+    }
+
+    /// Takes back every move.
+    pub fn restart(&mut self) {
+        self.replay_to(0);
+    }
+
+    /// Builds the current board again by playing the history from the start.
+    pub fn rebuild(&self) -> S {
+        let mut board = self.start.clone();
+        for &(position, player) in self.moves() {
+            board
+                .play_move(position, player)
+                .expect("moves in the history were legal when played");
+        }
+        board
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_undone_moves_until_a_different_one_is_played() {
+        let mut game = Game::new(Board::new());
+        for (position, player) in [(4, b'X'), (0, b'O'), (8, b'X')] {
+            game.play(position, player).unwrap();
+        }
+        game.replay_to(1);
+
+        game.play(0, b'O').unwrap();
+        assert_eq!(game.redo(), Some((8, b'X')));
+
+        game.replay_to(1);
+        game.play(2, b'O').unwrap();
+        assert_eq!(game.redo(), None);
+        assert_eq!(game.moves(), &[(4, b'X'), (2, b'O')]);
+    }
+}
@@ -1,540 +1,1032 @@
 mod agent;
+mod bitboard;
+mod checkpoint;
+mod error;
+mod exploration;
 mod game;
+mod history;
+mod mcts;
 mod mmagent;
+mod player;
+mod qagent;
+mod record;
+mod seed;
+mod states;
 mod stats;
+mod storage;
+mod tablebase;
+mod ultimate;
 
-use std::{collections::HashMap, io};
+use std::collections::HashMap;
+use std::io;
+use std::path::Path;
+use std::thread;
+use std::time::Instant;
 
-use agent::Agent;
-use game::Board;
-use stats::{Results};
-
-use crate::mmagent::minimax_search;
+use checkpoint::Checkpoint;
+use stats::Results;
 
 use rand::prelude::*;
 
-static MINIMAX: &str = "minimax";
-
-pub fn train(name: &str, cycles: usize) {
-    let mut p1 = Agent::new(name);
-    let mut p2 = Agent::new("");
+pub use agent::{
+    Agent, AgentConfig, AgentOptions, Algorithm, ExplorationOptions, LearnerState, ModelInfo,
+    TrainingStats,
+};
+pub use checkpoint::CheckpointConfig;
+pub use error::Error;
+pub use exploration::{Decay, Exploration, Strategy};
+pub use game::{Board, BoardError, GameState};
+pub use history::Game;
+pub use mcts::{Budget, Mcts, MctsConfig};
+pub use mmagent::{Difficulty, Minimax, MinimaxConfig};
+pub use player::{Command, Human, Learner, Player};
+pub use qagent::QAgent;
+pub use record::Record;
+pub use states::{enumerate_positions, is_reachable, validate_position, Position};
+pub use storage::{Storage, DEFAULT_MODELS_DIR};
+pub use tablebase::{Outcome, Solution, Tablebase, TablebasePlayer};
+pub use ultimate::UltimateBoard;
 
-    for i in 0..cycles {
-        if i % 1000 == 0 {
-            println!("{} cycles", i);
+static MINIMAX: &str = "minimax";
+static MCTS: &str = "mcts";
+static HUMAN: &str = "human";
+static TABLEBASE: &str = "tablebase";
+
+/// Number of games played when two non-human players are compared.
+static GAMES: usize = 100;
+
+/// Number of games each thread of a parallel training plays before what the
+/// threads learned is merged.
+static SYNC_GAMES: usize = 200;
+
+/// Creates an untrained agent for the game of `board`, learning with
+/// `agent_config.algorithm`.
+fn new_agent<S: GameState>(
+    name: &str,
+    agent_config: AgentConfig,
+    board: &S,
+) -> Box<dyn Learner<S>> {
+    let mut agent: Box<dyn Learner<S>> = match agent_config.algorithm {
+        Algorithm::Afterstate => Box::new(Agent::with_config(name, agent_config)),
+        Algorithm::QLearning | Algorithm::Sarsa => {
+            Box::new(QAgent::with_config(name, agent_config))
         }
+    };
 
-        if i == cycles / 2 {
-            println!("Switching symbols");
-            let tmp = p1.clone();
-            p1 = p2.clone();
-            p2 = tmp;
-        }
+    *agent.info_mut() = ModelInfo {
+        variant: Some(board.variant()),
+        created: Some(record::now()),
+        ..Default::default()
+    };
+    agent
+}
 
-        let mut board = Board::new();
+/// Loads the agent called `name`, which must have been trained already on the
+/// game of `board`, with the algorithm it was trained with.
+fn load_agent<S: GameState>(
+    name: &str,
+    agent_options: AgentOptions,
+    board: &S,
+    storage: &Storage,
+) -> Result<Box<dyn Learner<S>>, Error> {
+    let (info, values) = agent::read_model(name, board, storage)?;
+
+    let mut agent = new_agent(name, trained_config(info.config, agent_options), board);
+    agent.restore(info, values);
+    Ok(agent)
+}
 
-        loop {
-            let turn = board.get_turn();
+/// Loads the agent called `name` to be compared with another player: it
+/// plays what it learned without learning from these games, which leaves its
+/// model as it was.
+fn load_evaluated<S: GameState>(
+    name: &str,
+    agent_options: AgentOptions,
+    board: &S,
+    storage: &Storage,
+) -> Result<Box<dyn Learner<S>>, Error> {
+    let mut agent = load_agent(name, agent_options, board, storage)?;
+    agent.set_learning(false);
+    Ok(agent)
+}
 
-            let action = if turn == b'X' {
-                p1.get_best_action(&board, turn)
-            } else {
-                p2.get_best_action(&board, turn)
-            };
+/// The config of an agent saved with `trained_with`, with `agent_options`
+/// over it. The algorithm and whether values are stored by canonical hash
+/// always stay the ones the values were learned with, models saved before
+/// configs being afterstate ones with plain hashes.
+fn trained_config(trained_with: Option<AgentConfig>, agent_options: AgentOptions) -> AgentConfig {
+    let trained_with = trained_with.unwrap_or_default();
+    AgentConfig {
+        algorithm: trained_with.algorithm,
+        symmetric: trained_with.symmetric,
+        ..agent_options.apply(trained_with)
+    }
+}
 
-            if let Err(e) = board.play_move(action, turn) {
-                eprintln!("{}", e.value());
-                continue;
-            }
+/// A minimax player for the game of `board`, which reads the positions it
+/// solves from the tablebase of the board when one was saved in `storage`.
+fn new_minimax<S: GameState>(
+    board: &S,
+    minimax_config: MinimaxConfig,
+    storage: &Storage,
+) -> Result<Minimax, Error> {
+    let minimax = Minimax::new(minimax_config);
+
+    match board
+        .as_board()
+        .map(|board| Tablebase::load(board, storage))
+    {
+        Some(Ok(tablebase)) => Ok(minimax.with_tablebase(tablebase)),
+        // Without a tablebase, or a directory to find one in, it searches.
+        Some(Err(Error::Io(e))) if e.kind() == io::ErrorKind::NotFound => Ok(minimax),
+        Some(Err(Error::MissingModelsDir(_))) | None => Ok(minimax),
+        Some(Err(e)) => Err(e),
+    }
+}
 
-            if turn == b'X' {
-                p1.add_state(&board)
-            } else {
-                p2.add_state(&board)
-            };
+/// Plays `GAMES` games between two players, the first one starting the first
+/// half of them, and returns how each of them did.
+fn compare<S: GameState>(
+    board: &S,
+    (n1, p1): (&str, &mut dyn Player<S>),
+    (n2, p2): (&str, &mut dyn Player<S>),
+    seed: Option<u64>,
+    record: Option<&Path>,
+) -> Result<HashMap<String, Results>, Error> {
+    let mut wins: HashMap<String, Results> = HashMap::new();
 
-            let (is_finished, winner) = board.get_winner();
-
-            if is_finished {
-                if let Some(winner) = winner {
-                    if winner == b'X' {
-                        p1.feed_reward(1.0);
-                        p2.feed_reward(-1.0);
-                    } else {
-                        p1.feed_reward(-1.0);
-                        p2.feed_reward(1.0);
-                    }
-                } else {
-                    p2.feed_reward(0.5);
-                }
-                break;
-            };
+    println!();
+    for i in 0..GAMES {
+        if i < GAMES / 2 {
+            let winner = play_recorded(board, (n1, p1), (n2, p2), seed, record)?;
+            stats::record(&mut wins, n1, n2, winner);
+        } else {
+            let winner = play_recorded(board, (n2, p2), (n1, p1), seed, record)?;
+            stats::record(&mut wins, n2, n1, winner);
         }
-
-        p1.reset();
-        p2.reset();
     }
-    if let Err(e) = p2.save_model() {
-        eprintln!("{}", e);
-    }
-}
 
-pub fn train_with_minimax(name: &str, cycles: usize) {
-    let mut p1 = Agent::new(name);
+    Ok(wins)
+}
 
-    let mut agent = b'X';
-    let mut minimax = b'O';
+/// Plays a single game starting from `board` and returns the winner, if any.
+///
+/// Players may ask to take moves back on their turn, see `Command`.
+///
+/// Fails if the game is already over on `board` or if a player picks a move
+/// the rules do not allow.
+pub fn play_game<S: GameState>(
+    board: S,
+    x: &mut dyn Player<S>,
+    o: &mut dyn Player<S>,
+) -> Result<Option<u8>, Error> {
+    let game = run_game(board, x, o)?;
+    Ok(game.board().get_winner().1)
+}
 
-    for i in 0..cycles {
-        if i % 1000 == 0 {
-            println!("{} cycles", i);
-        }
+/// Plays a game like `play_game` and appends its record to the file at
+/// `record`, if any, along with the `seed` of the run.
+fn play_recorded<S: GameState>(
+    board: &S,
+    (x_name, x): (&str, &mut dyn Player<S>),
+    (o_name, o): (&str, &mut dyn Player<S>),
+    seed: Option<u64>,
+    record: Option<&Path>,
+) -> Result<Option<u8>, Error> {
+    let game = run_game(board.clone(), x, o)?;
+
+    if let Some(path) = record {
+        Record::from_game(&game, x_name, o_name, seed).append(path)?;
+    }
 
-        if i == cycles / 2 {
-            println!("Switching symbols");
-            agent = b'O';
-            minimax = b'X';
-        }
+    Ok(game.board().get_winner().1)
+}
 
+/// Plays a game and returns it once finished, with the moves that were not
+/// taken back.
+fn run_game<S: GameState>(
+    board: S,
+    x: &mut dyn Player<S>,
+    o: &mut dyn Player<S>,
+) -> Result<Game<S>, Error> {
+    if board.get_winner().0 {
+        return Err(Error::GameOver);
+    }
 
-        let mut board = Board::new();
+    let mut game = Game::new(board);
 
-        loop {
-            let turn = board.get_turn();
+    loop {
+        let turn = game.board().get_turn();
 
-            let action = if turn == agent {
-                p1.get_best_action(&board, agent)
-            } else {
-                mmagent::minimax_search(&board, minimax)
-            };
+        let command = if turn == b'X' {
+            x.choose_command(game.board(), turn)
+        } else {
+            o.choose_command(game.board(), turn)
+        };
 
-            if let Err(e) = board.play_move(action, turn) {
-                eprintln!("{}", e.value());
+        let position = match command {
+            Command::Move(position) => position,
+            Command::Undo => {
+                // Take back the opponent's reply too, so it is `turn` to move again.
+                undo(&mut game, x, o);
+                while game.ply() > 0 && game.board().get_turn() != turn {
+                    undo(&mut game, x, o);
+                }
                 continue;
             }
+            Command::Restart => {
+                while game.ply() > 0 {
+                    undo(&mut game, x, o);
+                }
+                continue;
+            }
+        };
 
-            if turn == agent {
-                p1.add_state(&board)
-            };
+        game.play(position, turn)?;
 
-            let (is_finished, winner) = board.get_winner();
-
-            if is_finished {
-                if let Some(winner) = winner {
-                    if winner == agent {
-                        p1.feed_reward(1.0);
-                    } else {
-                        p1.feed_reward(-1.0);
-                    }
-                } else if agent == b'O' {
-                    p1.feed_reward(0.5);
-                }
-                break;
-            };
-        }
+        x.observe_move(game.board(), position, turn, b'X');
+        o.observe_move(game.board(), position, turn, b'O');
 
-        p1.reset();
-    }
-    if let Err(e) = p1.save_model() {
-        eprintln!("{}", e);
+        let (is_finished, winner) = game.board().get_winner();
+
+        if is_finished {
+            x.observe_end(game.board(), winner, b'X');
+            o.observe_end(game.board(), winner, b'O');
+            return Ok(game);
+        }
     }
 }
 
-pub fn play(agent_name: &str) {
-    let mut p1 = Agent::new(agent_name);
-    if let Err(e) = p1.load_model() {
-        eprintln!("{}", e);
-        std::process::exit(1);
+/// Takes back the last move of `game`, if any, and tells both players.
+fn undo<S: GameState>(game: &mut Game<S>, x: &mut dyn Player<S>, o: &mut dyn Player<S>) {
+    if let Some((position, mover)) = game.undo() {
+        x.observe_undo(game.board(), position, mover, b'X');
+        o.observe_undo(game.board(), position, mover, b'O');
     }
+}
 
-    let mut board = Board::new();
-    let mut winner = board.get_winner();
+/// An agent to train and its self-play partner, with where their run stands.
+struct Training<S: GameState> {
+    agent: Box<dyn Learner<S>>,
+    partner: Box<dyn Learner<S>>,
+    config: AgentConfig,
+    checkpoints: CheckpointConfig,
+    /// How much the agent was trained before this run.
+    stats: TrainingStats,
+    /// The cycles already played when resuming from a checkpoint.
+    done: usize,
+    cycles: usize,
+    storage: Storage,
+}
+
+impl<S: GameState> Training<S> {
+    /// Sets up a run of `cycles` cycles training the agent called `name`, or
+    /// when resuming continues its checkpoint if it has one, and else trains
+    /// its saved model further with a partner starting from the same values.
+    ///
+    /// A checkpoint is continued with the cycles, config and checkpoint
+    /// interval it was saved with.
+    fn start(
+        name: &str,
+        cycles: usize,
+        agent_options: AgentOptions,
+        board: &S,
+        checkpoints: CheckpointConfig,
+        storage: &Storage,
+    ) -> Result<Self, Error> {
+        // Fails before training rather than once it is over if the model
+        // cannot be saved.
+        storage.path(name)?;
+
+        if !checkpoints.resume {
+            let agent_config = agent_options.config();
+            return Ok(Self {
+                agent: new_agent(name, agent_config, board),
+                partner: new_agent("", agent_config, board),
+                config: agent_config,
+                checkpoints,
+                stats: TrainingStats::default(),
+                done: 0,
+                cycles,
+                storage: storage.clone(),
+            });
+        }
+
+        if let Some(checkpoint) = Checkpoint::load(name, storage)? {
+            println!(
+                "Resuming the training of {} at cycle {} of {}",
+                name, checkpoint.cycle, checkpoint.cycles
+            );
+
+            let variant = board.variant();
+            if checkpoint.info.variant.as_ref() != Some(&variant) {
+                return Err(Error::IncompatibleModel(format!(
+                    "The checkpoint of {} is not for {}",
+                    name, variant
+                )));
+            }
+
+            let config = checkpoint.config;
+            let mut training = Self {
+                agent: new_agent(name, config, board),
+                partner: new_agent("", config, board),
+                config,
+                checkpoints: CheckpointConfig {
+                    every: Some(checkpoint.every),
+                    ..checkpoints
+                },
+                stats: checkpoint.info.stats,
+                done: checkpoint.cycle,
+                cycles: checkpoint.cycles,
+                storage: storage.clone(),
+            };
+            let partner = checkpoint
+                .partner
+                .unwrap_or_else(|| checkpoint.agent.clone());
+            training.agent.restore(checkpoint.info, checkpoint.agent);
+            *training.partner.values_mut() = partner;
+            training.reseed(checkpoint.cycle);
+
+            return Ok(training);
+        }
 
-    let agent_begins = rand::thread_rng().gen::<bool>();
+        let (info, values) = agent::read_model(name, board, storage)?;
+        println!("Training {} further from its saved model", name);
+        let config = trained_config(info.config, agent_options);
+
+        let mut partner = new_agent("", config, board);
+        *partner.values_mut() = values.clone();
+        let mut agent = new_agent(name, config, board);
+        let stats = info.stats;
+        agent.restore(info, values);
+
+        Ok(Self {
+            agent,
+            partner,
+            config,
+            checkpoints,
+            stats,
+            done: 0,
+            cycles,
+            storage: storage.clone(),
+        })
+    }
 
-    if !agent_begins {
-        println!("Starting board");
-        println!("{}", board);
+    /// Moves the random choices of both agents to the streams of the cycle
+    /// `done`.
+    fn reseed(&mut self, done: usize) {
+        let stream = format!("@{}", done);
+        self.agent.reseed(&stream);
+        self.partner.reseed(&stream);
     }
 
-    while !winner.0 {
-        let turn = board.get_turn();
+    /// Saves the model of the agent and a checkpoint of the run once `done`
+    /// cycles are played, the partner being left out when training against
+    /// minimax.
+    fn checkpoint(&mut self, name: &str, done: usize, with_partner: bool) -> Result<(), Error> {
+        self.save_model(done)?;
+
+        Checkpoint {
+            cycle: done,
+            cycles: self.cycles,
+            every: self.checkpoints.every.unwrap_or(done),
+            config: self.config,
+            info: ModelInfo {
+                stats: self.stats,
+                ..self.agent.info().clone()
+            },
+            agent: self.agent.values(),
+            partner: with_partner.then(|| self.partner.values()),
+        }
+        .save(name, &self.storage)?;
 
-        let field = if agent_begins {
-            if turn == b'X' {
-                p1.get_best_action(&board, turn)
-            } else {
-                read_human_input()
-            }
-        } else if turn == b'O' {
-            read_human_input()
-        } else {
-            p1.get_best_action(&board, turn)
+        self.reseed(done);
+        Ok(())
+    }
+
+    /// Saves the model of the agent once `done` cycles of the run are
+    /// played, counting them in its training stats.
+    fn save_model(&mut self, done: usize) -> Result<(), Error> {
+        self.agent.info_mut().stats = TrainingStats {
+            cycles: self.stats.cycles + done as u64,
+            runs: self.stats.runs + 1,
         };
+        self.agent.save_model(&self.storage)
+    }
 
-        if let Err(e) = board.play_move(field, turn) {
-            eprintln!("{}", e.value());
-            continue;
-        }
+    /// Saves the trained agent and removes the checkpoint of the run.
+    fn finish(&mut self, name: &str, start: Instant) -> Result<(), Error> {
+        report_speed(self.cycles - self.done, start);
+        self.save_model(self.cycles)?;
+        Checkpoint::remove(name, &self.storage)
+    }
+}
 
-        if agent_begins && turn == b'X' || !agent_begins && turn == b'O' {
-            p1.add_state(&board);
+/// Trains the agent called `name` by playing `cycles` games against a partner
+/// learning alongside it, playing X for the first half of them and O for the
+/// other.
+pub fn train<S: GameState>(
+    name: &str,
+    cycles: usize,
+    board: &S,
+    agent_options: AgentOptions,
+    checkpoints: CheckpointConfig,
+    storage: &Storage,
+) -> Result<(), Error> {
+    let start = Instant::now();
+    let mut training = Training::start(name, cycles, agent_options, board, checkpoints, storage)?;
+    let (resumed, cycles) = (training.done, training.cycles);
+
+    for i in resumed..cycles {
+        if i % 1000 == 0 {
+            println!("{} cycles", i);
         }
 
-        println!("Game after {}'s move", turn);
-        println!("{}", board);
+        if i == cycles / 2 {
+            println!("Switching symbols");
+        }
 
-        winner = board.get_winner();
-    }
+        let Training { agent, partner, .. } = &mut training;
+        let progress = i as f32 / cycles as f32;
+        agent.set_progress(progress);
+        partner.set_progress(progress);
 
-    let winner: String = if let Some(winner) = winner.1 {
-        if winner == b'X' && agent_begins || winner == b'O' && !agent_begins {
-            p1.feed_reward(1.0);
+        if i < cycles / 2 {
+            play_game(board.clone(), &mut **agent, &mut **partner)?;
         } else {
-            p1.feed_reward(-1.0);
+            play_game(board.clone(), &mut **partner, &mut **agent)?;
         }
-        winner.to_string()
-    } else {
-        if !agent_begins {
-            p1.feed_reward(0.5);
-        }
-        "nobody".to_string()
-    };
 
-    println!("The winner is {}.", winner);
-    p1.save_model().unwrap_or_else(|e| eprintln!("{}", e));
+        if training.checkpoints.due(i, i + 1, cycles) {
+            training.checkpoint(name, i + 1, true)?;
+        }
+    }
+    training.finish(name, start)
 }
 
-fn read_human_input() -> usize {
-    loop {
-        println!("Which field to set?");
+/// Trains like `train`, with the games spread over `threads` threads.
+///
+/// Each thread plays with its own copies of the two agents, and every
+/// `SYNC_GAMES` games of each thread the values they learned are merged back
+/// into the agents and handed out to the threads again.
+pub fn train_parallel<S: GameState + Sync>(
+    name: &str,
+    cycles: usize,
+    board: &S,
+    agent_options: AgentOptions,
+    threads: usize,
+    checkpoints: CheckpointConfig,
+    storage: &Storage,
+) -> Result<(), Error> {
+    let start = Instant::now();
+    let threads = threads.max(1);
+    let mut training = Training::start(name, cycles, agent_options, board, checkpoints, storage)?;
+    let cycles = training.cycles;
+
+    let fork = |training: &Training<S>, done: usize| -> Vec<_> {
+        (0..threads)
+            .map(|w| {
+                let (mut w1, mut w2) = (training.agent.fork(w), training.partner.fork(w));
+                let stream = format!("#{}@{}", w, done);
+                w1.reseed(&stream);
+                w2.reseed(&stream);
+                (w1, w2)
+            })
+            .collect()
+    };
+    let mut workers = fork(&training, training.done);
 
-        let mut position = String::new();
+    let mut played = training.done;
+    while played < cycles {
+        if played == cycles / 2 {
+            println!("Switching symbols");
+        }
 
-        if let Err(e) = io::stdin().read_line(&mut position) {
-            eprintln!("{}", e);
-            continue;
+        // Rounds stop at the switch, so that no game is played with the
+        // symbols the wrong way round.
+        let end = if played < cycles / 2 {
+            cycles / 2
+        } else {
+            cycles
         };
+        let round = (end - played).min(threads * SYNC_GAMES);
+
+        workers = thread::scope(|scope| {
+            let handles: Vec<_> = workers
+                .into_iter()
+                .enumerate()
+                .map(|(w, (mut w1, mut w2))| {
+                    scope.spawn(move || {
+                        for i in (played + w..played + round).step_by(threads) {
+                            let progress = i as f32 / cycles as f32;
+                            w1.set_progress(progress);
+                            w2.set_progress(progress);
+
+                            if i < cycles / 2 {
+                                play_game(board.clone(), &mut *w1, &mut *w2)?;
+                            } else {
+                                play_game(board.clone(), &mut *w2, &mut *w1)?;
+                            }
+                        }
+                        Ok((w1, w2))
+                    })
+                })
+                .collect();
+
+            handles
+                .into_iter()
+                .map(|h| h.join().expect("a training thread panicked"))
+                .collect::<Result<Vec<_>, Error>>()
+        })?;
+
+        let Training { agent, partner, .. } = &mut training;
+        let agent_values = merge(agent.values(), workers.iter().map(|(w1, _)| w1.values()));
+        let partner_values = merge(partner.values(), workers.iter().map(|(_, w2)| w2.values()));
+        for (w1, w2) in &mut workers {
+            w1.values_mut().clone_from(&agent_values);
+            w2.values_mut().clone_from(&partner_values);
+        }
+        *agent.values_mut() = agent_values;
+        *partner.values_mut() = partner_values;
 
-        match position.trim().parse::<usize>() {
-            Ok(v) => {
-                if (1..=9).contains(&v) {
-                    return v - 1;
-                } else {
-                    eprintln!("Number not between 1 and 9!");
-                    continue;
-                }
-            }
-            Err(e) => {
-                eprintln!("{}", e);
-                continue;
-            }
-        };
+        if (played + round) / 1000 != played / 1000 {
+            println!("{} cycles", played + round);
+        }
+
+        if training.checkpoints.due(played, played + round, cycles) {
+            training.checkpoint(name, played + round, true)?;
+            workers = fork(&training, played + round);
+        }
+        played += round;
     }
+    training.finish(name, start)
 }
 
-pub fn play_minimax() {
-    let mut board = Board::new();
-    let mut winner = board.get_winner();
-
-    let human_begins = rand::thread_rng().gen::<bool>();
+/// What copies of an agent holding `base` learned: each value becomes the
+/// mean of the copies that changed it, the others keeping their own.
+fn merge<'a>(
+    base: &HashMap<String, f32>,
+    copies: impl Iterator<Item = &'a HashMap<String, f32>>,
+) -> HashMap<String, f32> {
+    let mut sums: HashMap<&str, (f32, usize)> = HashMap::new();
+    for values in copies {
+        for (key, &value) in values {
+            if base.get(key) != Some(&value) {
+                let sum = sums.entry(key).or_insert((0.0, 0));
+                sum.0 += value;
+                sum.1 += 1;
+            }
+        }
+    }
 
-    if human_begins {
-        println!("Starting board");
-        println!("{}", board);
+    let mut merged = base.clone();
+    for (key, (sum, count)) in sums {
+        merged.insert(key.to_string(), sum / count as f32);
     }
+    merged
+}
 
-    while !winner.0 {
-        let turn = if board.get_turn() == b'X' { b'O' } else { b'X' };
+/// Prints how many training games were played per second since `start`.
+fn report_speed(games: usize, start: Instant) {
+    let seconds = start.elapsed().as_secs_f64();
+    println!(
+        "Played {} games in {:.1}s, {:.0} games/s",
+        games,
+        seconds,
+        games as f64 / seconds
+    );
+}
 
-        let field = if human_begins {
-            if turn == b'X' {
-                read_human_input()
-            } else {
-                mmagent::minimax_search(&board, b'O')
-            }
-        } else if turn == b'X' {
-            mmagent::minimax_search(&board, b'X')
-        } else {
-            read_human_input()
-        };
+/// Trains the agent called `name` by playing `cycles` games against minimax,
+/// playing X for the first half of them and O for the other.
+pub fn train_with_minimax<S: GameState>(
+    name: &str,
+    cycles: usize,
+    board: &S,
+    minimax_config: MinimaxConfig,
+    agent_options: AgentOptions,
+    checkpoints: CheckpointConfig,
+    storage: &Storage,
+) -> Result<(), Error> {
+    let start = Instant::now();
+    let mut training = Training::start(name, cycles, agent_options, board, checkpoints, storage)?;
+    let (resumed, cycles) = (training.done, training.cycles);
+
+    // Like the agents, minimax draws from the seed of the run once resumed.
+    let mut minimax = new_minimax(board, minimax_config, storage)?;
+    let run_seed = training.config.seed;
+    let reseed = |minimax: &mut Minimax, done: usize| {
+        minimax.reseed(seed::derive(run_seed, &format!("minimax@{}", done)));
+    };
+    if resumed > 0 {
+        reseed(&mut minimax, resumed);
+    }
 
-        if let Err(e) = board.play_move(field, turn) {
-            eprintln!("{}", e.value());
-            continue;
+    for i in resumed..cycles {
+        if i % 1000 == 0 {
+            println!("{} cycles", i);
         }
 
-        println!("Game after {}'s move", turn);
-        println!("{}", board);
+        if i == cycles / 2 {
+            println!("Switching symbols");
+        }
 
-        winner = board.get_winner();
-    }
+        let agent = &mut training.agent;
+        agent.set_progress(i as f32 / cycles as f32);
 
-    let winner: String = if let Some(winner) = winner.1 {
-        winner.to_string()
-    } else {
-        "nobody".to_string()
-    };
+        if i < cycles / 2 {
+            play_game(board.clone(), &mut **agent, &mut minimax)?;
+        } else {
+            play_game(board.clone(), &mut minimax, &mut **agent)?;
+        }
 
-    println!("The winner is {}.", winner);
+        if training.checkpoints.due(i, i + 1, cycles) {
+            training.checkpoint(name, i + 1, false)?;
+            reseed(&mut minimax, i + 1);
+        }
+    }
+    training.finish(name, start)
 }
 
-pub fn play_agent_vs_minimax(agent_name: &str) {
-    let mut wins: HashMap<String, Results> = HashMap::new();
-    let mut p1 = Agent::new(agent_name);
-    if let Err(e) = p1.load_model() {
-        eprintln!("{}", e);
-        std::process::exit(1);
+pub fn play<S: GameState>(
+    agent_name: &str,
+    board: &S,
+    agent_options: AgentOptions,
+    record: Option<&Path>,
+    storage: &Storage,
+) -> Result<(), Error> {
+    let mut p1 = load_agent(agent_name, agent_options, board, storage)?;
+
+    let mut human = Human::new();
+    let seed = agent_options.seed;
+
+    if seed::rng(seed::derive(seed, "starter")).gen::<bool>() {
+        play_recorded(
+            board,
+            (agent_name, &mut *p1),
+            (HUMAN, &mut human),
+            seed,
+            record,
+        )?;
+    } else {
+        play_recorded(
+            board,
+            (HUMAN, &mut human),
+            (agent_name, &mut *p1),
+            seed,
+            record,
+        )?;
     }
 
-    static GAMES: usize = 100;
+    p1.save_model(storage)
+}
 
-    println!();
-    for i in 0..GAMES {
-        let mut board = Board::new();
-        let mut winner = board.get_winner();
+pub fn play_minimax<S: GameState>(
+    board: &S,
+    minimax_config: MinimaxConfig,
+    record: Option<&Path>,
+    storage: &Storage,
+) -> Result<(), Error> {
+    let mut minimax = new_minimax(board, minimax_config, storage)?;
+    let mut human = Human::new();
+    let seed = minimax_config.seed;
+
+    if seed::rng(seed::derive(seed, "starter")).gen::<bool>() {
+        play_recorded(
+            board,
+            (HUMAN, &mut human),
+            (MINIMAX, &mut minimax),
+            seed,
+            record,
+        )?;
+    } else {
+        play_recorded(
+            board,
+            (MINIMAX, &mut minimax),
+            (HUMAN, &mut human),
+            seed,
+            record,
+        )?;
+    }
 
-        let agent_begins = i < GAMES / 2;
+    Ok(())
+}
 
-        while !winner.0 {
-            let turn = board.get_turn();
+pub fn play_agent_vs_minimax<S: GameState>(
+    agent_name: &str,
+    board: &S,
+    minimax_config: MinimaxConfig,
+    agent_options: AgentOptions,
+    record: Option<&Path>,
+    storage: &Storage,
+) -> Result<(), Error> {
+    let mut p1 = load_evaluated(agent_name, agent_options, board, storage)?;
+
+    let mut minimax = new_minimax(board, minimax_config, storage)?;
+
+    let wins = compare(
+        board,
+        (agent_name, &mut *p1),
+        (MINIMAX, &mut minimax),
+        agent_options.seed,
+        record,
+    )?;
 
-            let field = if agent_begins {
-                if turn == b'X' {
-                    p1.get_best_action(&board, turn)
-                } else {
-                    minimax_search(&board, b'O')
-                }
-            } else if turn == b'X' {
-                minimax_search(&board, b'X')
-            } else {
-                p1.get_best_action(&board, turn)
-            };
+    let agent = wins[agent_name];
+    let minimax = wins[MINIMAX];
 
-            if let Err(e) = board.play_move(field, turn) {
-                eprintln!("{}", e.value());
-                continue;
-            }
+    println!();
+    println!("Results:");
+    println!("Agent");
+    println!("{}", agent);
+    println!("Minimax");
+    println!("{}", minimax);
 
-            if agent_begins && turn == b'X' || !agent_begins && turn == b'O' {
-                p1.add_state(&board);
-            }
+    Ok(())
+}
 
-            winner = board.get_winner();
-        }
+pub fn play_multiple<S: GameState>(
+    a1: &str,
+    a2: &str,
+    board: &S,
+    agent_options: AgentOptions,
+    record: Option<&Path>,
+    storage: &Storage,
+) -> Result<(), Error> {
+    let mut p1 = load_evaluated(a1, agent_options, board, storage)?;
+    let mut p2 = load_evaluated(a2, agent_options, board, storage)?;
+
+    let wins = compare(
+        board,
+        (a1, &mut *p1),
+        (a2, &mut *p2),
+        agent_options.seed,
+        record,
+    )?;
+
+    let first = wins[a1];
+    let second = wins[a2];
 
-        if let Some(winner) = winner.1 {
-            if agent_begins {
-                if winner == b'X' {
-                    let entry = wins
-                        .entry(agent_name.to_string())
-                        .or_insert_with(Results::new);
-                    entry.playing_x += (1, 0, 0);
-                    let entry = wins.entry(MINIMAX.to_string()).or_insert_with(Results::new);
-                    entry.playing_o += (0, 0, 1);
-                } else {
-                    let entry = wins.entry(MINIMAX.to_string()).or_insert_with(Results::new);
-                    entry.playing_x += (1, 0, 0);
-                    let entry = wins
-                        .entry(agent_name.to_string())
-                        .or_insert_with(Results::new);
-                    entry.playing_o += (0, 0, 1);
-                }
-            } else if winner == b'X' {
-                let entry = wins.entry(MINIMAX.to_string()).or_insert_with(Results::new);
-                entry.playing_x += (1, 0, 0);
-                let entry = wins
-                    .entry(agent_name.to_string())
-                    .or_insert_with(Results::new);
-                entry.playing_o += (0, 0, 1);
-            } else {
-                let entry = wins
-                    .entry(agent_name.to_string())
-                    .or_insert_with(Results::new);
-                entry.playing_x += (1, 0, 0);
-                let entry = wins.entry(MINIMAX.to_string()).or_insert_with(Results::new);
-                entry.playing_o += (0, 0, 1);
-            }
+    println!();
+    println!("Results:");
+    println!("Agent {}", a1);
+    println!("{}", first);
+    println!("Agent {}", a2);
+    println!("{}", second);
 
-            if winner == b'X' && agent_begins || winner == b'O' && !agent_begins {
-                p1.feed_reward(1.0);
-            } else {
-                p1.feed_reward(-1.0);
-            }
-            winner.to_string()
-        } else {
+    Ok(())
+}
 
-            if agent_begins {
-                let entry = wins
-                    .entry(agent_name.to_string())
-                    .or_insert_with(Results::new);
-                entry.playing_x += (0, 1, 0);
-                let entry = wins.entry(MINIMAX.to_string()).or_insert_with(Results::new);
-                entry.playing_o += (0, 1, 0);
-            } else {
-                let entry = wins.entry(MINIMAX.to_string()).or_insert_with(Results::new);
-                entry.playing_x += (0, 1, 0);
-                let entry = wins
-                    .entry(agent_name.to_string())
-                    .or_insert_with(Results::new);
-                entry.playing_o += (0, 1, 0);
-            }
+pub fn play_mcts<S: GameState>(
+    board: &S,
+    config: MctsConfig,
+    record: Option<&Path>,
+) -> Result<(), Error> {
+    let mut mcts = Mcts::new(config);
+    let mut human = Human::new();
+    let seed = config.seed;
+
+    if seed::rng(seed::derive(seed, "starter")).gen::<bool>() {
+        play_recorded(board, (HUMAN, &mut human), (MCTS, &mut mcts), seed, record)?;
+    } else {
+        play_recorded(board, (MCTS, &mut mcts), (HUMAN, &mut human), seed, record)?;
+    }
 
-            if !agent_begins {
-                p1.feed_reward(0.5);
-            }
-            "nobody".to_string()
-        };
+    Ok(())
+}
 
-        p1.reset();
-    }
+pub fn play_agent_vs_mcts<S: GameState>(
+    agent_name: &str,
+    board: &S,
+    config: MctsConfig,
+    agent_options: AgentOptions,
+    record: Option<&Path>,
+    storage: &Storage,
+) -> Result<(), Error> {
+    let mut p1 = load_evaluated(agent_name, agent_options, board, storage)?;
+
+    let mut mcts = Mcts::new(config);
+
+    let wins = compare(
+        board,
+        (agent_name, &mut *p1),
+        (MCTS, &mut mcts),
+        agent_options.seed,
+        record,
+    )?;
 
     let agent = wins[agent_name];
-    let minimax = wins[MINIMAX];
+    let mcts = wins[MCTS];
 
     println!();
     println!("Results:");
     println!("Agent");
     println!("{}", agent);
+    println!("MCTS");
+    println!("{}", mcts);
+
+    Ok(())
+}
+
+pub fn play_mcts_vs_minimax<S: GameState>(
+    board: &S,
+    config: MctsConfig,
+    minimax_config: MinimaxConfig,
+    record: Option<&Path>,
+    storage: &Storage,
+) -> Result<(), Error> {
+    let mut mcts = Mcts::new(config);
+    let mut minimax = new_minimax(board, minimax_config, storage)?;
+
+    let wins = compare(
+        board,
+        (MCTS, &mut mcts),
+        (MINIMAX, &mut minimax),
+        config.seed,
+        record,
+    )?;
+
+    let mcts = wins[MCTS];
+    let minimax = wins[MINIMAX];
+
+    println!();
+    println!("Results:");
+    println!("MCTS");
+    println!("{}", mcts);
     println!("Minimax");
     println!("{}", minimax);
 
-    p1.save_model().unwrap_or_else(|e| eprintln!("{}", e));
+    Ok(())
 }
 
-pub fn play_multiple(a1: &str, a2: &str) {
-    let mut wins: HashMap<String, Results> = HashMap::new();
-    let mut p1 = Agent::new(a1);
-    if let Err(e) = p1.load_model() {
-        eprintln!("{}", e);
-        std::process::exit(1);
+pub fn play_tablebase(
+    board: &Board,
+    seed: Option<u64>,
+    record: Option<&Path>,
+    storage: &Storage,
+) -> Result<(), Error> {
+    let mut tablebase = TablebasePlayer::new(Tablebase::load_or_generate(board, storage)?, seed);
+    let mut human = Human::new();
+
+    if seed::rng(seed::derive(seed, "starter")).gen::<bool>() {
+        play_recorded(
+            board,
+            (HUMAN, &mut human),
+            (TABLEBASE, &mut tablebase),
+            seed,
+            record,
+        )?;
+    } else {
+        play_recorded(
+            board,
+            (TABLEBASE, &mut tablebase),
+            (HUMAN, &mut human),
+            seed,
+            record,
+        )?;
     }
 
-    let mut p2 = Agent::new(a1);
-    if let Err(e) = p1.load_model() {
-        eprintln!("{}", e);
-        std::process::exit(1);
-    }
+    Ok(())
+}
 
-    let mut rng = rand::thread_rng();
+pub fn play_agent_vs_tablebase(
+    agent_name: &str,
+    board: &Board,
+    agent_options: AgentOptions,
+    record: Option<&Path>,
+    storage: &Storage,
+) -> Result<(), Error> {
+    let mut p1 = load_evaluated(agent_name, agent_options, board, storage)?;
+
+    let mut tablebase = TablebasePlayer::new(
+        Tablebase::load_or_generate(board, storage)?,
+        agent_options.seed,
+    );
+
+    let wins = compare(
+        board,
+        (agent_name, &mut *p1),
+        (TABLEBASE, &mut tablebase),
+        agent_options.seed,
+        record,
+    )?;
+
+    let agent = wins[agent_name];
+    let tablebase = wins[TABLEBASE];
 
     println!();
-    for _ in 0..100 {
-        let mut board = Board::new();
-        let mut winner = board.get_winner();
+    println!("Results:");
+    println!("Agent");
+    println!("{}", agent);
+    println!("Tablebase");
+    println!("{}", tablebase);
+
+    Ok(())
+}
 
-        let agent1_begins = rng.gen::<bool>();
+/// Prints how many of the positions reachable from `board` the agent called
+/// `agent_name` has learned a value for.
+///
+/// As all of them are kept in memory, only games whose positions have a
+/// `GameState::get_index` are gone through, like for tablebases.
+pub fn coverage<S: GameState>(
+    agent_name: &str,
+    board: &S,
+    agent_options: AgentOptions,
+    storage: &Storage,
+) -> Result<(), Error> {
+    if board.get_index().is_none() {
+        return Err(Error::TooLarge(format!(
+            "The positions of {} are too many to check the coverage of an agent",
+            board.variant()
+        )));
+    }
 
-        while !winner.0 {
-            let turn = board.get_turn();
+    let agent = load_agent(agent_name, agent_options, board, storage)?;
 
-            let field = if agent1_begins {
-                if turn == b'X' {
-                    p1.get_best_action(&board, turn)
-                } else {
-                    p2.get_best_action(&board, turn)
-                }
-            } else if turn == b'X' {
-                p2.get_best_action(&board, turn)
-            } else {
-                p1.get_best_action(&board, turn)
-            };
+    let positions = enumerate_positions(board);
+    let known = positions.iter().filter(|p| agent.knows(&p.board)).count();
 
-            if let Err(e) = board.play_move(field, turn) {
-                eprintln!("{}", e.value());
-                continue;
-            }
+    println!(
+        "Agent {} knows {} of {} reachable positions ({:.1}%)",
+        agent_name,
+        known,
+        positions.len(),
+        100.0 * known as f64 / positions.len() as f64
+    );
 
-            if agent1_begins && turn == b'X' || !agent1_begins && turn == b'O' {
-                p1.add_state(&board)
-            } else {
-                p2.add_state(&board);
-            }
+    Ok(())
+}
 
-            winner = board.get_winner();
+/// Steps through game `index`, counting from 1, of the record file at `path`,
+/// showing the board after each move.
+pub fn replay(path: &Path, index: usize) -> Result<(), Error> {
+    let records = Record::read_all(path)?;
+    let record = index
+        .checked_sub(1)
+        .and_then(|i| records.get(i))
+        .ok_or_else(|| {
+            Error::InvalidRecord(format!(
+                "There is no game {} in {}, it holds {}",
+                index,
+                path.display(),
+                records.len()
+            ))
+        })?;
+
+    if record.variant == "ultimate" {
+        if record.position.is_some() {
+            return Err(Error::InvalidRecord(
+                "Ultimate games cannot start from a set up position".to_string(),
+            ));
         }
+        return replay_record(record, UltimateBoard::new());
+    }
 
-        if let Some(winner) = winner.1 {
-            if agent1_begins {
-                if winner == b'X' {
-                    let entry = wins
-                        .entry(a1.to_string())
-                        .or_insert_with(Results::new);
-                    entry.playing_x += (1, 0, 0);
-                    let entry = wins.entry(a2.to_string()).or_insert_with(Results::new);
-                    entry.playing_o += (0, 0, 1);
-                } else {
-                    let entry = wins.entry(a2.to_string()).or_insert_with(Results::new);
-                    entry.playing_x += (1, 0, 0);
-                    let entry = wins
-                        .entry(a1.to_string())
-                        .or_insert_with(Results::new);
-                    entry.playing_o += (0, 0, 1);
-                }
-            } else if winner == b'X' {
-                let entry = wins.entry(a2.to_string()).or_insert_with(Results::new);
-                entry.playing_x += (1, 0, 0);
-                let entry = wins
-                    .entry(a1.to_string())
-                    .or_insert_with(Results::new);
-                entry.playing_o += (0, 0, 1);
-            } else {
-                let entry = wins
-                    .entry(a1.to_string())
-                    .or_insert_with(Results::new);
-                entry.playing_x += (1, 0, 0);
-                let entry = wins.entry(a2.to_string()).or_insert_with(Results::new);
-                entry.playing_o += (0, 0, 1);
+    let size: Vec<usize> = record
+        .variant
+        .split('x')
+        .filter_map(|n| n.parse().ok())
+        .collect();
+
+    match size[..] {
+        [width, height, win_length] => {
+            let board = match &record.position {
+                Some(cells) => Board::from_cells(width, height, win_length, cells),
+                None => Board::with_size(width, height, win_length),
             }
+            .map_err(|e| Error::InvalidRecord(e.to_string()))?;
+            replay_record(record, board)
+        }
+        _ => Err(Error::InvalidRecord(format!(
+            "Unknown game {}",
+            record.variant
+        ))),
+    }
+}
 
-            if winner == b'X' && agent1_begins || winner == b'O' && !agent1_begins {
-                p1.feed_reward(1.0);
-                p2.feed_reward(-1.0);
-            } else {
-                p1.feed_reward(-1.0);
-                p2.feed_reward(1.0);
-            }
-            winner.to_string()
-        } else {
+fn replay_record<S: GameState>(record: &Record, start: S) -> Result<(), Error> {
+    let mut game = record.to_game(start)?;
+    let moves = game.moves().to_vec();
 
-            if agent1_begins {
-                let entry = wins
-                    .entry(a1.to_string())
-                    .or_insert_with(Results::new);
-                entry.playing_x += (0, 1, 0);
-                let entry = wins.entry(a2.to_string()).or_insert_with(Results::new);
-                entry.playing_o += (0, 1, 0);
-            } else {
-                let entry = wins.entry(a2.to_string()).or_insert_with(Results::new);
-                entry.playing_x += (0, 1, 0);
-                let entry = wins
-                    .entry(a1.to_string())
-                    .or_insert_with(Results::new);
-                entry.playing_o += (0, 1, 0);
-            }
+    println!("{} (X) against {} (O), {}", record.x, record.o, record.date);
+    game.restart();
+    println!("Starting board");
+    println!("{}", game.board());
 
-            if !agent1_begins {
-                p1.feed_reward(0.5);
-            } else {
-                p2.feed_reward(0.5);
-            }
-            "nobody".to_string()
-        };
+    for (ply, &(_, mover)) in moves.iter().enumerate() {
+        println!("Press Enter for the next move");
+        io::stdin().read_line(&mut String::new())?;
 
-        p1.reset();
-        p2.reset()
+        game.replay_to(ply + 1);
+        println!("{} plays {}", mover as char, record.moves[ply]);
+        println!("{}", game.board());
     }
 
-    let agent = wins[a1];
-    let minimax = wins[a2];
-
-    println!();
-    println!("Results:");
-    println!("Agent {}", a1);
-    println!("{}", agent);
-    println!("Agent {}", a2);
-    println!("{}", minimax);
+    match record.result {
+        Some(winner) => println!("The winner is {}.", winner as char),
+        None => println!("The winner is nobody."),
+    }
 
-    p1.save_model().unwrap_or_else(|e| eprintln!("{}", e));
-    p2.save_model().unwrap_or_else(|e| eprintln!("{}", e));
-}
\ No newline at end of file
+    Ok(())
+}
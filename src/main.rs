@@ -1,11 +1,94 @@
 #[macro_use]
 extern crate clap;
-use clap::App;
+use clap::{App, ArgMatches};
+use std::path::Path;
+use std::thread;
+use std::time::Duration;
+use tictactoe::{
+    AgentOptions, Algorithm, Board, Budget, CheckpointConfig, Decay, Difficulty, GameState,
+    MctsConfig, MinimaxConfig, Storage, Strategy, UltimateBoard,
+};
+
+/// How many plies the minimax player looks ahead in ultimate games when
+/// neither `--depth` nor the difficulty limit it, a full search is out of
+/// reach there.
+const ULTIMATE_DEPTH: usize = 4;
 
 fn main() {
     let yaml = load_yaml!("cli.yml");
     let matches = App::from_yaml(yaml).get_matches();
 
+    if let Some(matches) = matches.subcommand_matches("replay") {
+        let path = Path::new(matches.value_of("file").unwrap());
+        let game = value_t!(matches.value_of("game"), usize).unwrap_or_else(|e| e.exit());
+
+        if let Err(e) = tictactoe::replay(path, game) {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    if matches.is_present("ultimate") {
+        run(&matches, &UltimateBoard::new(), Some(ULTIMATE_DEPTH));
+        return;
+    }
+
+    if let Some(position) = matches.value_of("position") {
+        match position.parse::<Board>() {
+            Ok(board) => run_board(&matches, &board),
+            Err(e) => {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+
+    let width = value_t!(matches.value_of("width"), usize).unwrap_or_else(|e| e.exit());
+    let height = value_t!(matches.value_of("height"), usize).unwrap_or_else(|e| e.exit());
+    let win_length = value_t!(matches.value_of("win-length"), usize).unwrap_or_else(|e| e.exit());
+
+    let board = match Board::with_size(width, height, win_length) {
+        Ok(b) => b,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+
+    run_board(&matches, &board);
+}
+
+/// Runs the mode picked on the command line on a board of the m,n,k game,
+/// which unlike ultimate can also be played against a tablebase.
+fn run_board(matches: &ArgMatches, board: &Board) {
+    if !matches.is_present("tablebase") {
+        run(matches, board, None);
+        return;
+    }
+
+    let record = matches.value_of("record").map(Path::new);
+
+    let storage = storage(matches);
+
+    let result = if matches.is_present("agent") {
+        let agent_name = matches.value_of("agent-name").unwrap();
+        let agent_options = evaluated(agent_options(matches));
+        tictactoe::play_agent_vs_tablebase(agent_name, board, agent_options, record, &storage)
+    } else {
+        tictactoe::play_tablebase(board, seed(matches), record, &storage)
+    };
+
+    if let Err(e) = result {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
+}
+
+/// Runs the mode picked on the command line on `board`. `max_depth` caps the
+/// minimax search unless `--depth` asks for something else.
+fn run<S: GameState + Sync>(matches: &ArgMatches, board: &S, max_depth: Option<usize>) {
     let train = matches.is_present("training");
 
     let agent = matches.is_present("agent");
@@ -19,23 +102,240 @@ fn main() {
 
     let agents_num = matches.occurrences_of("agent");
 
-    println!("{}", agents_num);
+    let mcts = matches.is_present("mcts");
+
+    let symmetric = matches.is_present("symmetry");
 
-    if train {
+    let agent_options = agent_options(matches);
+
+    let evaluation_options = evaluated(agent_options);
+
+    let seed = seed(matches);
+
+    let difficulty =
+        value_t!(matches.value_of("difficulty"), Difficulty).unwrap_or_else(|e| e.exit());
+
+    let mut minimax_config = MinimaxConfig {
+        symmetric,
+        seed,
+        prefer_fast_wins: matches.is_present("fast-wins"),
+        ..Default::default()
+    }
+    .with_difficulty(difficulty);
+
+    if matches.is_present("depth") {
+        minimax_config.depth =
+            Some(value_t!(matches.value_of("depth"), usize).unwrap_or_else(|e| e.exit()));
+    } else if minimax_config.depth.is_none() {
+        minimax_config.depth = max_depth;
+    }
+
+    let record = matches.value_of("record").map(Path::new);
+
+    let storage = storage(matches);
+
+    let checkpoints = CheckpointConfig {
+        every: if matches.is_present("checkpoint") {
+            Some(value_t!(matches.value_of("checkpoint"), usize).unwrap_or_else(|e| e.exit()))
+        } else {
+            None
+        },
+        resume: matches.is_present("resume"),
+    };
+
+    let result = if matches.is_present("coverage") {
+        tictactoe::coverage(agent_name, board, agent_options, &storage)
+    } else if train {
+        if minimax {
+            if matches.occurrences_of("threads") > 0 {
+                eprintln!("Training against minimax runs on one thread, --threads only applies to self-play");
+                std::process::exit(1);
+            }
+            tictactoe::train_with_minimax(
+                agent_name,
+                training_cycles,
+                board,
+                minimax_config,
+                agent_options,
+                checkpoints,
+                &storage,
+            )
+        } else {
+            match threads(matches) {
+                1 => tictactoe::train(
+                    agent_name,
+                    training_cycles,
+                    board,
+                    agent_options,
+                    checkpoints,
+                    &storage,
+                ),
+                threads => tictactoe::train_parallel(
+                    agent_name,
+                    training_cycles,
+                    board,
+                    agent_options,
+                    threads,
+                    checkpoints,
+                    &storage,
+                ),
+            }
+        }
+    } else if mcts {
+        let config = mcts_config(matches);
         if minimax {
-            tictactoe::train_with_minimax(agent_name, training_cycles)
+            tictactoe::play_mcts_vs_minimax(board, config, minimax_config, record, &storage)
+        } else if agent {
+            tictactoe::play_agent_vs_mcts(
+                agent_name,
+                board,
+                config,
+                evaluation_options,
+                record,
+                &storage,
+            )
         } else {
-            tictactoe::train(agent_name, training_cycles);
+            tictactoe::play_mcts(board, config, record)
         }
     } else if minimax {
         if agent {
-            tictactoe::play_agent_vs_minimax(agent_name);
+            tictactoe::play_agent_vs_minimax(
+                agent_name,
+                board,
+                minimax_config,
+                evaluation_options,
+                record,
+                &storage,
+            )
         } else {
-            tictactoe::play_minimax()
+            tictactoe::play_minimax(board, minimax_config, record, &storage)
         }
     } else if agents_num > 1 {
-        tictactoe::play_multiple("a1", "a2")
+        tictactoe::play_multiple("a1", "a2", board, evaluation_options, record, &storage)
+    } else {
+        tictactoe::play(agent_name, board, agent_options, record, &storage)
+    };
+
+    if let Err(e) = result {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
+}
+
+/// `options` for agents compared with another player, which play greedily
+/// unless another strategy is given.
+fn evaluated(mut options: AgentOptions) -> AgentOptions {
+    options.exploration.strategy.get_or_insert(Strategy::Greedy);
+    options
+}
+
+fn mcts_config(matches: &ArgMatches) -> MctsConfig {
+    let budget = if matches.is_present("time") {
+        let millis = value_t!(matches.value_of("time"), u64).unwrap_or_else(|e| e.exit());
+        Budget::Time(Duration::from_millis(millis))
+    } else {
+        Budget::Iterations(
+            value_t!(matches.value_of("iterations"), usize).unwrap_or_else(|e| e.exit()),
+        )
+    };
+
+    MctsConfig {
+        budget,
+        exploration: value_t!(matches.value_of("mcts-exploration"), f64)
+            .unwrap_or_else(|e| e.exit()),
+        seed: seed(matches),
+    }
+}
+
+/// How many threads to train on, `--threads 0` asking for one per core.
+fn threads(matches: &ArgMatches) -> usize {
+    match value_t!(matches.value_of("threads"), usize).unwrap_or_else(|e| e.exit()) {
+        0 => thread::available_parallelism().map_or(1, |n| n.get()),
+        n => n,
+    }
+}
+
+/// The seed of every random choice of the run, `None` to draw them from
+/// entropy.
+fn seed(matches: &ArgMatches) -> Option<u64> {
+    if matches.is_present("seed") {
+        Some(value_t!(matches.value_of("seed"), u64).unwrap_or_else(|e| e.exit()))
     } else {
-        tictactoe::play(agent_name)
+        None
     }
 }
+
+/// Where agents, checkpoints and tablebases are kept, `--models-dir` or else
+/// the default directory.
+fn storage(matches: &ArgMatches) -> Storage {
+    matches
+        .value_of("models-dir")
+        .map_or_else(Storage::default, Storage::new)
+}
+
+/// The agent's hyperparameters given for the run: those of `--agent-config`
+/// and over them the ones given as options.
+fn agent_options(matches: &ArgMatches) -> AgentOptions {
+    let mut options = match matches.value_of("agent-config") {
+        Some(path) => AgentOptions::load(Path::new(path)).unwrap_or_else(|e| {
+            eprintln!("Cannot read the agent config {}: {}", path, e);
+            std::process::exit(1);
+        }),
+        None => AgentOptions::default(),
+    };
+
+    if matches.is_present("algorithm") {
+        options.algorithm =
+            Some(value_t!(matches.value_of("algorithm"), Algorithm).unwrap_or_else(|e| e.exit()));
+    }
+
+    if matches.is_present("explore") {
+        options.exploration.strategy =
+            Some(value_t!(matches.value_of("explore"), Strategy).unwrap_or_else(|e| e.exit()));
+    }
+
+    if matches.is_present("decay") {
+        options.exploration.decay =
+            Some(value_t!(matches.value_of("decay"), Decay).unwrap_or_else(|e| e.exit()));
+    }
+
+    if matches.is_present("exploration-rate") {
+        let rate = value_t!(matches.value_of("exploration-rate"), f32).unwrap_or_else(|e| e.exit());
+        options.exploration.start = Some(rate);
+        options.exploration.end = Some(rate);
+    }
+
+    let values = [
+        ("exploration-end", &mut options.exploration.end),
+        ("learning-rate", &mut options.learning_rate),
+        ("discount", &mut options.discount),
+        ("lambda", &mut options.lambda),
+        ("win-reward", &mut options.win_reward),
+        ("draw-reward", &mut options.draw_reward),
+        ("x-draw-reward", &mut options.x_draw_reward),
+        ("loss-reward", &mut options.loss_reward),
+        ("initial-value", &mut options.initial_value),
+    ];
+
+    for (name, value) in values {
+        if matches.is_present(name) {
+            *value = Some(value_t!(matches.value_of(name), f32).unwrap_or_else(|e| e.exit()));
+        }
+    }
+
+    if matches.is_present("symmetry") {
+        options.symmetric = Some(true);
+    }
+
+    if matches.is_present("seed") {
+        options.seed = seed(matches);
+    }
+
+    let config = options.config();
+    if config.symmetric && config.algorithm != Algorithm::Afterstate {
+        eprintln!("Symmetry only applies to the afterstate algorithm, q-learning and sarsa keep the values of each position apart");
+        std::process::exit(1);
+    }
+
+    options
+}
@@ -0,0 +1,211 @@
+use crate::game::GameState;
+use crate::player::Player;
+use crate::seed;
+use rand::{prelude::*, rngs::StdRng, seq::SliceRandom};
+use std::time::{Duration, Instant};
+
+/// How long the search runs for before picking a move.
+#[derive(Debug, Clone, Copy)]
+pub enum Budget {
+    Iterations(usize),
+    Time(Duration),
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct MctsConfig {
+    pub budget: Budget,
+    /// The `c` in UCT, higher values make the search try less visited moves.
+    pub exploration: f64,
+    /// Seeds the random moves of the search, so that its games can be
+    /// reproduced.
+    pub seed: Option<u64>,
+}
+
+impl Default for MctsConfig {
+    fn default() -> Self {
+        Self {
+            budget: Budget::Iterations(1000),
+            exploration: std::f64::consts::SQRT_2,
+            seed: None,
+        }
+    }
+}
+
+struct Node<S> {
+    state: S,
+    parent: Option<usize>,
+    /// The move leading here from the parent and who played it.
+    position: Option<usize>,
+    mover: u8,
+    children: Vec<usize>,
+    untried: Vec<usize>,
+    visits: u32,
+    /// Sum of the results for `mover`: 1 for a win, 0.5 for a draw.
+    score: f64,
+}
+
+/// A player running Monte Carlo Tree Search with UCT selection and uniformly
+/// random rollouts.
+pub struct Mcts {
+    config: MctsConfig,
+    rng: StdRng,
+}
+
+impl Mcts {
+    pub fn new(config: MctsConfig) -> Self {
+        Self {
+            config,
+            rng: seed::rng(seed::derive(config.seed, "mcts")),
+        }
+    }
+
+    pub fn search<S: GameState>(&mut self, board: &S, symbol: u8) -> usize {
+        let mut tree = vec![Node {
+            state: board.clone(),
+            parent: None,
+            position: None,
+            mover: other(symbol),
+            children: vec![],
+            untried: untried(board),
+            visits: 0,
+            score: 0.0,
+        }];
+
+        let start = Instant::now();
+        let mut iterations = 0;
+
+        loop {
+            match self.config.budget {
+                Budget::Iterations(n) if iterations >= n => break,
+                Budget::Time(t) if start.elapsed() >= t => break,
+                _ => {}
+            }
+            iterations += 1;
+
+            let leaf = self.select(&tree);
+            let node = self.expand(&mut tree, leaf);
+            let winner = self.rollout(&tree[node].state);
+            backpropagate(&mut tree, node, winner);
+        }
+
+        tree[0]
+            .children
+            .iter()
+            .max_by_key(|&&c| tree[c].visits)
+            .and_then(|&c| tree[c].position)
+            .unwrap_or_else(|| *board.get_available().choose(&mut self.rng).unwrap())
+    }
+
+    /// Walks down the tree through fully expanded nodes, following the child
+    /// with the best UCT score.
+    fn select<S: GameState>(&self, tree: &[Node<S>]) -> usize {
+        let mut node = 0;
+
+        while tree[node].untried.is_empty() && !tree[node].children.is_empty() {
+            let ln_visits = (tree[node].visits as f64).ln();
+            node = *tree[node]
+                .children
+                .iter()
+                .max_by(|&&a, &&b| {
+                    let uct = |c: usize| {
+                        let child = &tree[c];
+                        child.score / child.visits as f64
+                            + self.config.exploration * (ln_visits / child.visits as f64).sqrt()
+                    };
+                    uct(a).total_cmp(&uct(b))
+                })
+                .unwrap();
+        }
+
+        node
+    }
+
+    /// Adds a child for one of the untried moves of `node`, unless the game is
+    /// over there.
+    fn expand<S: GameState>(&mut self, tree: &mut Vec<Node<S>>, node: usize) -> usize {
+        if tree[node].untried.is_empty() {
+            return node;
+        }
+
+        let index = self.rng.gen_range(0..tree[node].untried.len());
+        let position = tree[node].untried.swap_remove(index);
+
+        let mut state = tree[node].state.clone();
+        let mover = state.get_turn();
+        if state.play_move(position, mover).is_err() {
+            return node;
+        }
+
+        let untried = untried(&state);
+        tree.push(Node {
+            state,
+            parent: Some(node),
+            position: Some(position),
+            mover,
+            children: vec![],
+            untried,
+            visits: 0,
+            score: 0.0,
+        });
+
+        let child = tree.len() - 1;
+        tree[node].children.push(child);
+        child
+    }
+
+    /// Plays random moves until the end of the game and returns the winner.
+    fn rollout<S: GameState>(&mut self, state: &S) -> Option<u8> {
+        let mut state = state.clone();
+
+        loop {
+            let (is_finished, winner) = state.get_winner();
+            if is_finished {
+                return winner;
+            }
+
+            let turn = state.get_turn();
+            let position = *state.get_available().choose(&mut self.rng).unwrap();
+            if state.play_move(position, turn).is_err() {
+                return None;
+            }
+        }
+    }
+}
+
+fn backpropagate<S>(tree: &mut [Node<S>], node: usize, winner: Option<u8>) {
+    let mut current = Some(node);
+
+    while let Some(n) = current {
+        tree[n].visits += 1;
+        tree[n].score += match winner {
+            Some(w) if w == tree[n].mover => 1.0,
+            Some(_) => 0.0,
+            None => 0.5,
+        };
+        current = tree[n].parent;
+    }
+}
+
+fn other(symbol: u8) -> u8 {
+    if symbol == b'X' {
+        b'O'
+    } else {
+        b'X'
+    }
+}
+
+/// The moves left to expand from `state`, none once the game is over even if
+/// cells are still empty.
+fn untried<S: GameState>(state: &S) -> Vec<usize> {
+    if state.get_winner().0 {
+        vec![]
+    } else {
+        state.get_available()
+    }
+}
+
+impl<S: GameState> Player<S> for Mcts {
+    fn choose_move(&mut self, board: &S, symbol: u8) -> usize {
+        self.search(board, symbol)
+    }
+}
@@ -1,123 +1,452 @@
-use crate::game::Board;
+use crate::game::GameState;
+use crate::player::Player;
+use crate::seed;
+use crate::tablebase::{Outcome, Tablebase};
+use cached::{Cached, SizedCache};
+use rand::{rngs::StdRng, seq::SliceRandom, Rng};
+use std::str::FromStr;
 
-pub fn minimax_search(board: &Board, my_sign: u8) -> usize {
-    let (_, action) = max_value(&board.board, i32::MIN, i32::MAX, my_sign);
-    action.unwrap()
+/// How many positions the transposition table of a `Minimax` player keeps.
+const TABLE_SIZE: usize = 1 << 20;
+
+/// Score of a won game. Heuristic evaluations stay far below it, so anything
+/// above `WIN / 2` is a forced win.
+const WIN: i32 = 1000;
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MinimaxConfig {
+    /// How many plies to look ahead, `None` searches until the end of the
+    /// game. Positions the search stops at are scored with
+    /// `GameState::evaluate`.
+    pub depth: Option<usize>,
+    /// Shares transposition table entries between rotations and reflections
+    /// of a position.
+    pub symmetric: bool,
+    /// Seeds the choice among equally good moves, for reproducible games.
+    pub seed: Option<u64>,
+    /// Scores wins higher the sooner they happen and losses higher the later
+    /// they happen, instead of treating all of them the same.
+    pub prefer_fast_wins: bool,
+    /// Chance of playing a random legal move instead of searching.
+    pub blunder_probability: f64,
 }
 
-fn max_value(board: &[u8; 9], alpha: i32, beta: i32, my_sign: u8) -> (i32, Option<usize>) {
-    let winner = check_winner(board, my_sign);
-    if let Some(result) = winner {
-        return (result, None);
+impl MinimaxConfig {
+    /// Sets the search depth and the blunder probability of `difficulty`.
+    pub fn with_difficulty(self, difficulty: Difficulty) -> Self {
+        let (depth, blunder_probability) = match difficulty {
+            Difficulty::Easy => (Some(1), 0.3),
+            Difficulty::Medium => (Some(2), 0.1),
+            Difficulty::Hard => (Some(4), 0.0),
+            Difficulty::Perfect => (None, 0.0),
+        };
+
+        Self {
+            depth,
+            blunder_probability,
+            ..self
+        }
     }
+}
 
-    let mut alpha = alpha;
+/// Named strengths for the minimax player, from one that only sees its next
+/// move and often plays at random to one that never loses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Difficulty {
+    Easy,
+    Medium,
+    Hard,
+    Perfect,
+}
+
+impl FromStr for Difficulty {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "easy" => Ok(Difficulty::Easy),
+            "medium" => Ok(Difficulty::Medium),
+            "hard" => Ok(Difficulty::Hard),
+            "perfect" => Ok(Difficulty::Perfect),
+            _ => Err(format!("Unknown difficulty {}", s)),
+        }
+    }
+}
 
-    let mut max = i32::MIN;
-    let mut action = None;
+/// How a stored value relates to the real value of the position: alpha-beta
+/// cutoffs only prove a bound on it.
+#[derive(Clone, Copy)]
+enum Bound {
+    Exact,
+    Lower,
+    Upper,
+}
 
-    for p in get_availables(board) {
-        let mut new_board = *board;
-        new_board[p] = my_sign;
+#[derive(Clone, Copy)]
+struct Entry {
+    value: i32,
+    bound: Bound,
+    depth: Option<usize>,
+}
 
-        let (v, _) = min_value(&new_board, alpha, beta, my_sign);
-        if v > max {
-            max = v;
-            action = Some(p);
+impl Entry {
+    /// Whether the entry was searched at least as deep as `depth` asks for.
+    fn covers(&self, depth: Option<usize>) -> bool {
+        match (self.depth, depth) {
+            (None, _) => true,
+            (Some(_), None) => false,
+            (Some(stored), Some(wanted)) => stored >= wanted,
         }
+    }
+}
 
-        alpha = alpha.max(v);
+/// Values of the positions already searched, keyed on the board hash and the
+/// symbol the search is maximizing for.
+struct TranspositionTable {
+    cache: SizedCache<(String, u8), Entry>,
+    symmetric: bool,
+}
 
-        if beta <= alpha {
-            return (max, action);
+impl TranspositionTable {
+    fn new(symmetric: bool) -> Self {
+        Self {
+            cache: SizedCache::with_size(TABLE_SIZE),
+            symmetric,
         }
     }
 
-    (max, action)
+    fn key<S: GameState>(&self, board: &S, my_sign: u8) -> (String, u8) {
+        let hash = if self.symmetric {
+            board.get_canonical_hash()
+        } else {
+            board.get_hash()
+        };
+        (hash, my_sign)
+    }
+
+    /// The entry for `key`, if one was searched deep enough.
+    fn get(&mut self, key: &(String, u8), depth: Option<usize>) -> Option<Entry> {
+        self.cache
+            .cache_get(key)
+            .copied()
+            .filter(|e| e.covers(depth))
+    }
+
+    fn set(&mut self, key: (String, u8), entry: Entry) {
+        self.cache.cache_set(key, entry);
+    }
 }
 
-fn min_value(board: &[u8; 9], alpha: i32, beta: i32, my_sign: u8) -> (i32, Option<usize>) {
-    let winner = check_winner(board, my_sign);
-    if let Some(result) = winner {
-        return (result, None);
+/// A player that answers with the minimax move, picking at random among the
+/// moves that are equally good and remembering the positions it has searched
+/// across moves and games.
+pub struct Minimax {
+    config: MinimaxConfig,
+    table: TranspositionTable,
+    /// Solves the positions it has without searching them.
+    tablebase: Option<Tablebase>,
+    rng: StdRng,
+}
+
+impl Minimax {
+    pub fn new(config: MinimaxConfig) -> Self {
+        Self {
+            config,
+            table: TranspositionTable::new(config.symmetric),
+            tablebase: None,
+            rng: seed::rng(seed::derive(config.seed, "minimax")),
+        }
+    }
+
+    /// Reads the value of the positions `tablebase` has from it instead of
+    /// searching them, when the search would go to the end of the game.
+    pub fn with_tablebase(self, tablebase: Tablebase) -> Self {
+        Self {
+            tablebase: Some(tablebase),
+            ..self
+        }
+    }
+
+    /// Draws the next random choices from `seed` instead of the seed of the
+    /// config.
+    pub fn reseed(&mut self, seed: Option<u64>) {
+        self.rng = seed::rng(seed);
     }
 
-    let mut beta = beta;
+    pub fn minimax_search<S: GameState>(&mut self, board: &S, my_sign: u8) -> usize {
+        if self.rng.gen::<f64>() < self.config.blunder_probability {
+            return *board.get_available().choose(&mut self.rng).unwrap();
+        }
+
+        let moves = self.root_values(board, my_sign);
+        let best = moves.iter().map(|&(_, v)| v).max().unwrap();
+
+        let best_moves: Vec<usize> = moves
+            .into_iter()
+            .filter(|&(_, v)| v == best)
+            .map(|(p, _)| p)
+            .collect();
+
+        *best_moves.choose(&mut self.rng).unwrap()
+    }
 
-    let mut min = i32::MAX;
-    let mut action: Option<usize> = None;
+    /// The exact value of every move available on `board`.
+    ///
+    /// The root is searched here rather than in `max_value`, with a full
+    /// window for each move so ties are real ties, and since the table only
+    /// holds values and its entries may come from a mirrored position.
+    fn root_values<S: GameState>(&mut self, board: &S, my_sign: u8) -> Vec<(usize, i32)> {
+        let depth = self.config.depth.map(|d| d.saturating_sub(1));
+        let mut values = vec![];
 
-    for p in get_availables(board) {
-        let mut new_board = *board;
-        new_board[p] = if my_sign == b'X' { b'O' } else { b'X' };
+        for p in board.get_available() {
+            let mut new_board = board.clone();
+            if new_board.play_move(p, my_sign).is_err() {
+                continue;
+            }
 
-        let (v, _) = max_value(&new_board, alpha, beta, my_sign);
-        if v < min {
-            min = v;
-            action = Some(p);
+            let v = match self.solved(&new_board) {
+                Some(v) => v,
+                None => self.min_value(&new_board, i32::MIN, i32::MAX, my_sign, depth, 1),
+            };
+            values.push((p, v));
         }
 
-        beta = beta.min(v);
+        values
+    }
 
-        if beta <= alpha {
-            return (min, action);
+    /// The value of `board` after one of the moves at the root, as the search
+    /// would find it, read from the tablebase if it has the position.
+    fn solved<S: GameState>(&self, board: &S) -> Option<i32> {
+        if self.config.depth.is_some() {
+            return None;
         }
+        let solution = self.tablebase.as_ref()?.lookup(board.as_board()?)?;
+
+        // The outcome is the opponent's, who moves next, and the game ends
+        // `distance` plies after this one.
+        let win = self.win_value(1 + solution.distance as i32);
+        Some(match solution.outcome {
+            Outcome::Win => -win,
+            Outcome::Draw => 0,
+            Outcome::Loss => win,
+        })
     }
 
-    (min, action)
-}
+    fn max_value<S: GameState>(
+        &mut self,
+        board: &S,
+        alpha: i32,
+        beta: i32,
+        my_sign: u8,
+        depth: Option<usize>,
+        ply: i32,
+    ) -> i32 {
+        let winner = self.check_winner(board, my_sign, ply);
+        if let Some(result) = winner {
+            return result;
+        }
 
-fn check_winner(board: &[u8; 9], my_sign: u8) -> Option<i32> {
-    for i in 0..3 {
-        if equals_3(board[i], board[i + 3], board[i + 6]) {
-            if board[i] == my_sign {
-                return Some(10);
-            } else {
-                return Some(-10);
+        if depth == Some(0) {
+            return board.evaluate(my_sign);
+        }
+
+        let key = self.table.key(board, my_sign);
+        let (mut alpha, mut beta) = (alpha, beta);
+        if let Some(v) = self.probe(&key, depth, ply, &mut alpha, &mut beta) {
+            return v;
+        }
+        let alpha_start = alpha;
+
+        let mut max = i32::MIN;
+
+        for p in board.get_available() {
+            let mut new_board = board.clone();
+            if new_board.play_move(p, my_sign).is_err() {
+                continue;
             }
+
+            let v = self.min_value(
+                &new_board,
+                alpha,
+                beta,
+                my_sign,
+                depth.map(|d| d - 1),
+                ply + 1,
+            );
+            max = max.max(v);
+
+            alpha = alpha.max(v);
+
+            if beta <= alpha {
+                break;
+            }
+        }
+
+        self.store(key, max, alpha_start, beta, depth, ply);
+        max
+    }
+
+    fn min_value<S: GameState>(
+        &mut self,
+        board: &S,
+        alpha: i32,
+        beta: i32,
+        my_sign: u8,
+        depth: Option<usize>,
+        ply: i32,
+    ) -> i32 {
+        let winner = self.check_winner(board, my_sign, ply);
+        if let Some(result) = winner {
+            return result;
+        }
+
+        if depth == Some(0) {
+            return board.evaluate(my_sign);
         }
 
-        if equals_3(board[i * 3], board[i * 3 + 1], board[i * 3 + 2]) {
-            if board[i * 3] == my_sign {
-                return Some(10);
-            } else {
-                return Some(-10);
+        let key = self.table.key(board, my_sign);
+        let (mut alpha, mut beta) = (alpha, beta);
+        if let Some(v) = self.probe(&key, depth, ply, &mut alpha, &mut beta) {
+            return v;
+        }
+        let beta_start = beta;
+
+        let mut min = i32::MAX;
+
+        for p in board.get_available() {
+            let mut new_board = board.clone();
+            let other_sign = if my_sign == b'X' { b'O' } else { b'X' };
+            if new_board.play_move(p, other_sign).is_err() {
+                continue;
+            }
+
+            let v = self.max_value(
+                &new_board,
+                alpha,
+                beta,
+                my_sign,
+                depth.map(|d| d - 1),
+                ply + 1,
+            );
+            min = min.min(v);
+
+            beta = beta.min(v);
+
+            if beta <= alpha {
+                break;
             }
         }
+
+        self.store(key, min, alpha, beta_start, depth, ply);
+        min
     }
 
-    if equals_3(board[0], board[4], board[8]) {
-        if board[0] == my_sign {
-            return Some(10);
+    /// The score of a win at `ply`.
+    fn win_value(&self, ply: i32) -> i32 {
+        if self.config.prefer_fast_wins {
+            WIN - ply
         } else {
-            return Some(-10);
+            WIN
+        }
+    }
+
+    fn check_winner<S: GameState>(&self, board: &S, my_sign: u8, ply: i32) -> Option<i32> {
+        let win = self.win_value(ply);
+
+        match board.get_winner() {
+            (true, Some(winner)) if winner == my_sign => Some(win),
+            (true, Some(_)) => Some(-win),
+            (true, None) => Some(0),
+            (false, _) => None,
         }
     }
 
-    if equals_3(board[2], board[4], board[6]) {
-        if board[2] == my_sign {
-            return Some(10);
+    /// Looks `key` up, narrowing the window with the bound found. Returns the
+    /// value when it settles the position.
+    fn probe(
+        &mut self,
+        key: &(String, u8),
+        depth: Option<usize>,
+        ply: i32,
+        alpha: &mut i32,
+        beta: &mut i32,
+    ) -> Option<i32> {
+        let entry = self.table.get(key, depth)?;
+        let value = self.to_root_relative(entry.value, ply);
+
+        match entry.bound {
+            Bound::Exact => return Some(value),
+            Bound::Lower => *alpha = (*alpha).max(value),
+            Bound::Upper => *beta = (*beta).min(value),
+        }
+
+        if *alpha >= *beta {
+            Some(value)
         } else {
-            return Some(-10);
+            None
         }
     }
 
-    if board.iter().filter(|&&c| c == b' ').count() == 0 {
-        return Some(0);
+    /// Stores `value`, found searching with the window `alpha`..`beta`.
+    fn store(
+        &mut self,
+        key: (String, u8),
+        value: i32,
+        alpha: i32,
+        beta: i32,
+        depth: Option<usize>,
+        ply: i32,
+    ) {
+        let bound = if value <= alpha {
+            Bound::Upper
+        } else if value >= beta {
+            Bound::Lower
+        } else {
+            Bound::Exact
+        };
+
+        let value = self.to_node_relative(value, ply);
+        self.table.set(
+            key,
+            Entry {
+                value,
+                bound,
+                depth,
+            },
+        );
+    }
+
+    /// With `prefer_fast_wins`, wins and losses are scored by their distance
+    /// from the root. The table stores them by their distance from the
+    /// position itself instead, so that they hold from any other root.
+    fn to_node_relative(&self, value: i32, ply: i32) -> i32 {
+        match value {
+            _ if !self.config.prefer_fast_wins => value,
+            v if v > WIN / 2 => v + ply,
+            v if v < -WIN / 2 => v - ply,
+            v => v,
+        }
     }
 
-    None
+    fn to_root_relative(&self, value: i32, ply: i32) -> i32 {
+        match value {
+            _ if !self.config.prefer_fast_wins => value,
+            v if v > WIN / 2 => v - ply,
+            v if v < -WIN / 2 => v + ply,
+            v => v,
+        }
+    }
 }
 
-fn equals_3(a: u8, b: u8, c: u8) -> bool {
-    a == b && b == c && a != b' '
+impl Default for Minimax {
+    fn default() -> Self {
+        Self::new(MinimaxConfig::default())
+    }
 }
 
-fn get_availables(board: &[u8; 9]) -> Vec<usize> {
-    board
-        .iter()
-        .enumerate()
-        .filter(|(_, &e)| e == b' ')
-        .map(|(i, _)| i)
-        .collect()
+impl<S: GameState> Player<S> for Minimax {
+    fn choose_move(&mut self, board: &S, symbol: u8) -> usize {
+        self.minimax_search(board, symbol)
+    }
 }
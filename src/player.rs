@@ -0,0 +1,222 @@
+use crate::agent::{LearnerState, ModelInfo};
+use crate::error::Error;
+use crate::game::{Board, GameState};
+use crate::storage::Storage;
+use std::collections::HashMap;
+use std::io;
+
+/// What a player wants to do when it is its turn.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Command {
+    Move(usize),
+    /// Take back moves until it is this player's turn again.
+    Undo,
+    /// Take back every move and start the game over.
+    Restart,
+}
+
+/// Anything that can take a seat at the board: a human, a trained agent or a
+/// search algorithm.
+pub trait Player<S: GameState = Board> {
+    /// Returns the position where `symbol` should be played on `board`.
+    fn choose_move(&mut self, board: &S, symbol: u8) -> usize;
+
+    /// Returns what to do on `board`, which is playing the move from
+    /// `choose_move` unless the player can ask for something else.
+    fn choose_command(&mut self, board: &S, symbol: u8) -> Command {
+        Command::Move(self.choose_move(board, symbol))
+    }
+
+    /// Called after every move, by either side, once it is on the board.
+    /// `me` is the symbol this player is playing with.
+    fn observe_move(&mut self, _board: &S, _position: usize, _mover: u8, _me: u8) {}
+
+    /// Called for every move taken back, with `board` as it is without it.
+    fn observe_undo(&mut self, _board: &S, _position: usize, _mover: u8, _me: u8) {}
+
+    /// Called once the game has finished, `winner` is `None` on a draw.
+    fn observe_end(&mut self, _board: &S, _winner: Option<u8>, _me: u8) {}
+}
+
+/// A player learning from its games, which keeps what it learned in
+/// `policy_{name}` in the models directory of a `Storage`.
+///
+/// Learners can be sent to other threads, so that several copies of one can
+/// train at the same time, see `fork`.
+pub trait Learner<S: GameState = Board>: Player<S> + Send {
+    /// What the player keeps as a learner, which the other methods work on.
+    fn learner(&self) -> &LearnerState;
+
+    fn learner_mut(&mut self) -> &mut LearnerState;
+
+    /// Takes over a model read from disk: what it records about the training
+    /// and its values.
+    fn restore(&mut self, info: ModelInfo, values: HashMap<String, f32>) {
+        let learner = self.learner_mut();
+        learner.info = info;
+        learner.values = values;
+    }
+
+    /// Saves what the player learned as its model in `storage`.
+    fn save_model(&self, storage: &Storage) -> Result<(), Error> {
+        self.learner().save_model(storage)
+    }
+
+    /// What is saved with the model besides the values.
+    fn info(&self) -> &ModelInfo {
+        &self.learner().info
+    }
+
+    fn info_mut(&mut self) -> &mut ModelInfo {
+        &mut self.learner_mut().info
+    }
+
+    /// What the player learned so far, as saved by `save_model`.
+    fn values(&self) -> &HashMap<String, f32> {
+        &self.learner().values
+    }
+
+    fn values_mut(&mut self) -> &mut HashMap<String, f32> {
+        &mut self.learner_mut().values
+    }
+
+    /// Draws the player's next random choices from the stream `stream` of its
+    /// own, so that they can be drawn again from the same point.
+    fn reseed(&mut self, stream: &str) {
+        self.learner_mut().reseed(stream);
+    }
+
+    /// A copy of the player for the training thread `worker`, drawing its
+    /// random choices from a stream of its own.
+    fn fork(&self, worker: usize) -> Box<dyn Learner<S>>;
+
+    /// Tells the player how much of its training is done, from 0 to 1, which
+    /// moves the exploration along its schedule.
+    fn set_progress(&mut self, progress: f32) {
+        self.learner_mut().progress = progress;
+    }
+
+    /// Stops or resumes learning from the games played, so that the player
+    /// can be evaluated without changing what it learned.
+    fn set_learning(&mut self, learning: bool) {
+        self.learner_mut().learning = learning;
+    }
+
+    /// Whether the player has learned anything about `board`.
+    fn knows(&self, board: &S) -> bool;
+}
+
+/// A player reading its moves from stdin and printing the game as it goes.
+///
+/// Besides a cell, `undo` takes back the last move of each side and `restart`
+/// starts the game over.
+pub struct Human {
+    /// Set at the start of a game and once moves are taken back, to show the
+    /// board before the next move.
+    redraw: bool,
+}
+
+impl Human {
+    pub fn new() -> Self {
+        Self { redraw: true }
+    }
+}
+
+impl Default for Human {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<S: GameState> Player<S> for Human {
+    fn choose_move(&mut self, board: &S, symbol: u8) -> usize {
+        loop {
+            match self.choose_command(board, symbol) {
+                Command::Move(position) => return position,
+                _ => eprintln!("Moves cannot be taken back in this game!"),
+            }
+        }
+    }
+
+    fn choose_command(&mut self, board: &S, symbol: u8) -> Command {
+        let available = board.get_available();
+
+        if available.len() == board.cells() {
+            println!("Starting board");
+            println!("{}", board);
+        } else if self.redraw {
+            println!("Current board");
+            println!("{}", board);
+        }
+        self.redraw = false;
+
+        loop {
+            match read_human_input(board.cells()) {
+                // The board says why, as in ultimate the cell may be free
+                // but in a board the move cannot go to.
+                Command::Move(position) if !available.contains(&position) => {
+                    match board.clone().play_move(position, symbol) {
+                        Err(e) => eprintln!("{}!", e),
+                        Ok(()) => eprintln!("Position {} cannot be played!", position + 1),
+                    }
+                }
+                command => return command,
+            }
+        }
+    }
+
+    fn observe_move(&mut self, board: &S, _position: usize, mover: u8, _me: u8) {
+        println!("Game after {}'s move", mover as char);
+        println!("{}", board);
+    }
+
+    fn observe_undo(&mut self, _board: &S, position: usize, mover: u8, _me: u8) {
+        println!("Took back {}'s move in {}", mover as char, position + 1);
+        self.redraw = true;
+    }
+
+    fn observe_end(&mut self, _board: &S, winner: Option<u8>, _me: u8) {
+        let winner: String = if let Some(winner) = winner {
+            (winner as char).to_string()
+        } else {
+            "nobody".to_string()
+        };
+
+        println!("The winner is {}.", winner);
+        self.redraw = true;
+    }
+}
+
+fn read_human_input(cells: usize) -> Command {
+    loop {
+        println!("Which field to set? (or undo, restart)");
+
+        let mut position = String::new();
+
+        if let Err(e) = io::stdin().read_line(&mut position) {
+            eprintln!("{}", e);
+            continue;
+        };
+
+        match position.trim() {
+            "u" | "undo" => return Command::Undo,
+            "r" | "restart" => return Command::Restart,
+            _ => {}
+        }
+
+        match position.trim().parse::<usize>() {
+            Ok(v) => {
+                if (1..=cells).contains(&v) {
+                    return Command::Move(v - 1);
+                } else {
+                    eprintln!("Number not between 1 and {}!", cells);
+                    continue;
+                }
+            }
+            Err(e) => {
+                eprintln!("{}", e);
+                continue;
+            }
+        };
+    }
+}
@@ -0,0 +1,108 @@
+use crate::agent::{AgentConfig, Algorithm, LearnerState};
+use crate::game::GameState;
+use crate::player::{Learner, Player};
+
+/// An agent learning the value of every move in every position, `Q(s, a)`,
+/// with a temporal difference update after each of its moves: Q-learning or
+/// SARSA depending on `AgentConfig::algorithm`.
+///
+/// Values are stored under the hash of the position followed by the move, as
+/// in `"X   O    :3"`. Symmetric positions do not share them.
+#[derive(Debug, Clone)]
+pub struct QAgent {
+    learner: LearnerState,
+    /// The key of the last move played, whose value is updated once the
+    /// agent moves again or the game ends.
+    last: Option<String>,
+}
+
+fn key(hash: &str, position: usize) -> String {
+    format!("{}:{}", hash, position)
+}
+
+impl QAgent {
+    pub fn with_config(name: &str, config: AgentConfig) -> Self {
+        Self {
+            learner: LearnerState::new(name, config),
+            last: None,
+        }
+    }
+
+    /// Moves the value of the last move played towards `target`.
+    fn update(&mut self, target: f32) {
+        if let Some(key) = self.last.take().filter(|_| self.learner.learning) {
+            let entry = self
+                .learner
+                .values
+                .entry(key)
+                .or_insert(self.learner.config.initial_value);
+            *entry += self.learner.config.learning_rate * (target - *entry);
+        }
+    }
+}
+
+impl<S: GameState> Learner<S> for QAgent {
+    fn learner(&self) -> &LearnerState {
+        &self.learner
+    }
+
+    fn learner_mut(&mut self) -> &mut LearnerState {
+        &mut self.learner
+    }
+
+    fn fork(&self, worker: usize) -> Box<dyn Learner<S>> {
+        let mut copy = self.clone();
+        copy.learner.reseed(&format!("#{}", worker));
+        Box::new(copy)
+    }
+
+    /// The agent learns values of the moves in the positions it plays from.
+    fn knows(&self, board: &S) -> bool {
+        let hash = board.get_hash();
+        board
+            .get_available()
+            .into_iter()
+            .any(|p| self.learner.values.contains_key(&key(&hash, p)))
+    }
+}
+
+impl<S: GameState> Player<S> for QAgent {
+    fn choose_move(&mut self, board: &S, _symbol: u8) -> usize {
+        let hash = board.get_hash();
+        let moves: Vec<(usize, f32)> = board
+            .get_available()
+            .into_iter()
+            .map(|p| (p, self.learner.value(&key(&hash, p))))
+            .collect();
+
+        let position = self.learner.config.exploration.choose(
+            &moves,
+            self.learner.progress,
+            &mut self.learner.rng,
+        );
+
+        // The opponent's reply gives no reward, so the previous move is only
+        // worth what can be expected from here.
+        let target = match self.learner.config.algorithm {
+            Algorithm::Sarsa => self.learner.value(&key(&hash, position)),
+            _ => moves.iter().map(|&(_, v)| v).fold(f32::MIN, f32::max),
+        };
+        self.update(self.learner.config.discount * target);
+        self.last = Some(key(&hash, position));
+
+        position
+    }
+
+    fn observe_undo(&mut self, _board: &S, _position: usize, mover: u8, me: u8) {
+        if mover == me {
+            self.last = None;
+        }
+    }
+
+    fn observe_end(&mut self, _board: &S, winner: Option<u8>, me: u8) {
+        match self.learner.config.reward(winner, me) {
+            Some(reward) => self.update(reward),
+            None => self.last = None,
+        }
+    }
+}
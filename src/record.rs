@@ -0,0 +1,314 @@
+use crate::error::Error;
+use crate::game::GameState;
+use crate::history::Game;
+use std::fmt;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// A finished game as kept in record files.
+///
+/// A record is plain text: a few `[Tag "value"]` lines followed by the moves,
+/// numbered in pairs as in chess notation:
+///
+/// ```text
+/// [Date "2026-10-18"]
+/// [Game "3x3x3"]
+/// [X "a1"]
+/// [O "human"]
+/// [Result "O"]
+/// 1. b2 a1 2. c3 b1 3. a3 c1
+/// ```
+///
+/// Cells are named by their column letter and row number counted from the top
+/// left, `a1` to `c3` on the classic board, and cell numbers such as `5` are
+/// read too. Games that did not start from an empty board also have a
+/// `Position` tag with the starting cells, `.` standing for an empty one, and
+/// games played with `--seed` have a `Seed` tag to play them again. A file
+/// holds any number of records separated by blank lines.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Record {
+    pub date: String,
+    /// The game played, see `GameState::variant`.
+    pub variant: String,
+    /// The starting cells as `Board::from_cells` reads them, `None` for an
+    /// empty board.
+    pub position: Option<String>,
+    /// Who played X and O: `human`, an agent's name, `minimax` or `mcts`.
+    pub x: String,
+    pub o: String,
+    /// The seed the players' random choices were drawn from, if any.
+    pub seed: Option<u64>,
+    /// The winner's symbol, `None` for a draw.
+    pub result: Option<u8>,
+    /// The moves in the order they were played, in record notation.
+    pub moves: Vec<String>,
+}
+
+impl Record {
+    /// Describes a game played today by `x` and `o` with random choices drawn
+    /// from `seed`.
+    pub fn from_game<S: GameState>(game: &Game<S>, x: &str, o: &str, seed: Option<u64>) -> Self {
+        let start = game.start();
+        let width = start.width();
+        let position = if start.get_available().len() == start.cells() {
+            None
+        } else {
+            Some(start.get_hash().replace(' ', "."))
+        };
+
+        Self {
+            date: today(),
+            variant: start.variant(),
+            position,
+            x: x.to_string(),
+            o: o.to_string(),
+            seed,
+            result: game.board().get_winner().1,
+            moves: game
+                .moves()
+                .iter()
+                .map(|&(position, _)| cell_name(position, width))
+                .collect(),
+        }
+    }
+
+    /// Plays the moves of the record on `start`, which must be the board of
+    /// the game it describes.
+    pub fn to_game<S: GameState>(&self, start: S) -> Result<Game<S>, Error> {
+        let (width, cells) = (start.width(), start.cells());
+        let mut game = Game::new(start);
+
+        for name in &self.moves {
+            let position = parse_cell(name, width, cells)
+                .ok_or_else(|| Error::InvalidRecord(format!("Unknown cell {}", name)))?;
+            let turn = game.board().get_turn();
+            game.play(position, turn)
+                .map_err(|e| Error::InvalidRecord(format!("Move {}: {}", name, e)))?;
+        }
+
+        Ok(game)
+    }
+
+    /// Adds the record at the end of the file at `path`, creating it if needed.
+    pub fn append(&self, path: &Path) -> Result<(), Error> {
+        let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+        writeln!(file, "{}", self)?;
+        Ok(())
+    }
+
+    /// Reads every record in the file at `path`.
+    pub fn read_all(path: &Path) -> Result<Vec<Self>, Error> {
+        Self::parse_all(&fs::read_to_string(path)?)
+    }
+
+    /// Parses records separated by blank lines.
+    pub fn parse_all(text: &str) -> Result<Vec<Self>, Error> {
+        let mut records = vec![];
+        let mut lines = vec![];
+
+        for line in text.lines().map(str::trim).chain(std::iter::once("")) {
+            if !line.is_empty() {
+                lines.push(line);
+            } else if !lines.is_empty() {
+                records.push(Self::parse(&lines)?);
+                lines.clear();
+            }
+        }
+
+        Ok(records)
+    }
+
+    fn parse(lines: &[&str]) -> Result<Self, Error> {
+        let mut tags = vec![];
+        let mut moves = vec![];
+
+        for line in lines {
+            if let Some(tag) = line.strip_prefix('[') {
+                let (name, value) = tag
+                    .strip_suffix(']')
+                    .and_then(|t| t.split_once(' '))
+                    .ok_or_else(|| Error::InvalidRecord(format!("Malformed tag {}", line)))?;
+                tags.push((name, value.trim().trim_matches('"')));
+            } else {
+                moves.extend(
+                    line.split_whitespace()
+                        .filter(|token| !token.ends_with('.'))
+                        .map(str::to_string),
+                );
+            }
+        }
+
+        let tag = |name: &str| {
+            tags.iter()
+                .find(|(n, _)| *n == name)
+                .map(|(_, v)| v.to_string())
+                .ok_or_else(|| Error::InvalidRecord(format!("Missing tag {}", name)))
+        };
+
+        let result = match tag("Result")?.as_str() {
+            "X" => Some(b'X'),
+            "O" => Some(b'O'),
+            "draw" => None,
+            r => return Err(Error::InvalidRecord(format!("Unknown result {}", r))),
+        };
+
+        Ok(Self {
+            date: tag("Date").unwrap_or_else(|_| "?".to_string()),
+            variant: tag("Game")?,
+            position: tag("Position").ok(),
+            x: tag("X")?,
+            o: tag("O")?,
+            seed: match tag("Seed") {
+                Ok(seed) => Some(
+                    seed.parse()
+                        .map_err(|_| Error::InvalidRecord(format!("Invalid seed {}", seed)))?,
+                ),
+                Err(_) => None,
+            },
+            result,
+            moves,
+        })
+    }
+}
+
+impl fmt::Display for Record {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let result = match self.result {
+            Some(winner) => (winner as char).to_string(),
+            None => "draw".to_string(),
+        };
+
+        writeln!(f, "[Date \"{}\"]", self.date)?;
+        writeln!(f, "[Game \"{}\"]", self.variant)?;
+        if let Some(position) = &self.position {
+            writeln!(f, "[Position \"{}\"]", position)?;
+        }
+        writeln!(f, "[X \"{}\"]", self.x)?;
+        writeln!(f, "[O \"{}\"]", self.o)?;
+        if let Some(seed) = self.seed {
+            writeln!(f, "[Seed \"{}\"]", seed)?;
+        }
+        writeln!(f, "[Result \"{}\"]", result)?;
+
+        let moves: Vec<String> = self
+            .moves
+            .chunks(2)
+            .enumerate()
+            .map(|(i, pair)| format!("{}. {}", i + 1, pair.join(" ")))
+            .collect();
+        writeln!(f, "{}", moves.join(" "))
+    }
+}
+
+/// Names `position` by column letter and row number, falling back to the
+/// cell number on boards too wide for letters.
+pub fn cell_name(position: usize, width: usize) -> String {
+    if width > 26 {
+        return (position + 1).to_string();
+    }
+
+    let (row, col) = (position / width, position % width);
+    format!("{}{}", (b'a' + col as u8) as char, row + 1)
+}
+
+/// Reads a cell written as by `cell_name` or as its number, from 1.
+pub fn parse_cell(name: &str, width: usize, cells: usize) -> Option<usize> {
+    let position = if let Ok(number) = name.parse::<usize>() {
+        number.checked_sub(1)?
+    } else {
+        let col = name.bytes().next().filter(u8::is_ascii_lowercase)? - b'a';
+        let row = name[1..].parse::<usize>().ok()?.checked_sub(1)?;
+        if col as usize >= width {
+            return None;
+        }
+        row * width + col as usize
+    };
+
+    (position < cells).then_some(position)
+}
+
+/// Today's date in UTC as `YYYY-MM-DD`.
+fn today() -> String {
+    now()[..10].to_string()
+}
+
+/// The current time in UTC as `YYYY-MM-DDTHH:MM:SSZ`.
+pub(crate) fn now() -> String {
+    let seconds = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0) as i64;
+    let days = seconds / 86_400;
+
+    // Converts days since 1970-01-01 to a civil date, counting years from
+    // March so that leap days fall at the end of them.
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        seconds % 86_400 / 3600,
+        seconds % 3600 / 60,
+        seconds % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_written_records_back() {
+        let won = Record {
+            date: "2026-10-18".to_string(),
+            variant: "3x3x3".to_string(),
+            position: None,
+            x: "a1".to_string(),
+            o: "human".to_string(),
+            seed: None,
+            result: Some(b'O'),
+            moves: ["b2", "a1", "c3", "b1", "a3", "c1"]
+                .iter()
+                .map(|m| m.to_string())
+                .collect(),
+        };
+        let drawn = Record {
+            date: "2026-10-19".to_string(),
+            variant: "4x4x3".to_string(),
+            position: Some("X...O...........".to_string()),
+            x: "minimax".to_string(),
+            o: "mcts".to_string(),
+            seed: Some(7),
+            result: None,
+            moves: vec!["b2".to_string()],
+        };
+
+        // As `append` writes them, each followed by a blank line.
+        let text = format!("{}\n{}\n", won, drawn);
+        assert_eq!(Record::parse_all(&text).unwrap(), vec![won, drawn]);
+    }
+
+    #[test]
+    fn parses_cell_names_back() {
+        for width in [3, 5, 27] {
+            let cells = width * 4;
+            for position in 0..cells {
+                let name = cell_name(position, width);
+                assert_eq!(parse_cell(&name, width, cells), Some(position), "{}", name);
+            }
+        }
+    }
+}
@@ -0,0 +1,26 @@
+use rand::{rngs::StdRng, SeedableRng};
+
+/// A generator seeded with `seed`, or from entropy without one.
+pub fn rng(seed: Option<u64>) -> StdRng {
+    match seed {
+        Some(seed) => StdRng::seed_from_u64(seed),
+        None => StdRng::from_entropy(),
+    }
+}
+
+/// Derives the seed of one component of a run from the seed of the run, so
+/// that components seeded alike still draw different numbers. `stream` names
+/// the component, such as an agent's name.
+pub fn derive(seed: Option<u64>, stream: &str) -> Option<u64> {
+    // FNV-1a of the stream, mixed into the seed by a SplitMix64 step.
+    let stream = stream.bytes().fold(0xcbf2_9ce4_8422_2325_u64, |hash, b| {
+        (hash ^ b as u64).wrapping_mul(0x0100_0000_01b3)
+    });
+
+    seed.map(|seed| {
+        let mut z = (seed ^ stream).wrapping_add(0x9e37_79b9_7f4a_7c15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    })
+}
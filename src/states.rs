@@ -0,0 +1,202 @@
+use crate::game::{Board, BoardError, GameState};
+use std::collections::HashMap;
+
+/// A position reachable from the start of the game.
+#[derive(Clone)]
+pub struct Position<S = Board> {
+    pub board: S,
+    /// Who moves next, even once the game is over.
+    pub turn: u8,
+    /// Whether the game is over.
+    pub terminal: bool,
+    /// The winner if both players play perfectly from here, `None` for a
+    /// draw.
+    pub value: Option<u8>,
+}
+
+/// Every position reachable from `start` by legal moves, `start` included,
+/// each counted once. There are 5478 on the empty 3x3 board.
+///
+/// All of them are kept in memory, which only works for small boards.
+pub fn enumerate_positions<S: GameState>(start: &S) -> Vec<Position<S>> {
+    let mut positions = vec![];
+    let mut seen = HashMap::new();
+    visit(start, &mut positions, &mut seen);
+    positions
+}
+
+/// Adds `board` and the positions after it, returning the index of `board`.
+fn visit<S: GameState>(
+    board: &S,
+    positions: &mut Vec<Position<S>>,
+    seen: &mut HashMap<String, usize>,
+) -> usize {
+    if let Some(&index) = seen.get(&board.get_hash()) {
+        return index;
+    }
+
+    let index = positions.len();
+    let turn = board.get_turn();
+    let (terminal, winner) = board.get_winner();
+    seen.insert(board.get_hash(), index);
+    positions.push(Position {
+        board: board.clone(),
+        turn,
+        terminal,
+        value: winner,
+    });
+
+    if terminal {
+        return index;
+    }
+
+    let mut values = vec![];
+    for position in board.get_available() {
+        let mut next = board.clone();
+        if next.play_move(position, turn).is_ok() {
+            let child = visit(&next, positions, seen);
+            values.push(positions[child].value);
+        }
+    }
+
+    // The player to move picks a win over a draw over a loss.
+    positions[index].value = if values.contains(&Some(turn)) {
+        Some(turn)
+    } else if values.contains(&None) {
+        None
+    } else {
+        values.first().copied().flatten()
+    };
+
+    index
+}
+
+/// Checks that `board` can come up in a game started by X: X has as many
+/// symbols as O or one more, at most one player has a line, and the winner, if
+/// any, completed it with the last move.
+pub fn validate_position(board: &Board) -> Result<(), BoardError> {
+    let count = |symbol| board.symbols().iter().filter(|&&c| c == symbol).count();
+    let (x, o) = (count(b'X'), count(b'O'));
+
+    if x != o && x != o + 1 {
+        return Err(BoardError::InvalidPosition(format!(
+            "X has {} symbols and O {}, but X starts and they take turns",
+            x, o
+        )));
+    }
+
+    let winner = match (board.has_line(b'X'), board.has_line(b'O')) {
+        (true, true) => {
+            return Err(BoardError::InvalidPosition(
+                "Both players have a line".to_string(),
+            ))
+        }
+        (true, false) if x == o => {
+            return Err(BoardError::InvalidPosition(
+                "O played after X won".to_string(),
+            ))
+        }
+        (false, true) if x > o => {
+            return Err(BoardError::InvalidPosition(
+                "X played after O won".to_string(),
+            ))
+        }
+        (true, false) => b'X',
+        (false, true) => b'O',
+        (false, false) => return Ok(()),
+    };
+
+    // Some symbol of the winner must have been the last move, so the board
+    // before it had no line yet.
+    let last_move = (0..board.cells()).any(|position| {
+        let mut before = board.clone();
+        before.get(position) == winner && {
+            before.set(position, b' ');
+            !before.has_line(winner)
+        }
+    });
+
+    if last_move {
+        Ok(())
+    } else {
+        Err(BoardError::InvalidPosition(format!(
+            "{} kept playing after winning",
+            winner as char
+        )))
+    }
+}
+
+/// Whether `board` can come up in a game, see `validate_position`.
+pub fn is_reachable(board: &Board) -> bool {
+    validate_position(board).is_ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A board of `width` with `cells` set row by row, without checking that
+    /// the position is reachable.
+    fn board(width: usize, win_length: usize, cells: &str) -> Board {
+        let mut board = Board::with_size(width, cells.len() / width, win_length).unwrap();
+        for (position, c) in cells.bytes().enumerate() {
+            if c != b'.' {
+                board.set(position, c);
+            }
+        }
+        board
+    }
+
+    fn invalid(reason: &str) -> Result<(), BoardError> {
+        Err(BoardError::InvalidPosition(reason.to_string()))
+    }
+
+    #[test]
+    fn enumerates_every_position_of_3x3() {
+        assert_eq!(enumerate_positions(&Board::new()).len(), 5478);
+    }
+
+    #[test]
+    fn accepts_every_enumerated_position() {
+        for position in enumerate_positions(&Board::new()) {
+            assert_eq!(validate_position(&position.board), Ok(()));
+        }
+    }
+
+    #[test]
+    fn rejects_two_winners() {
+        assert_eq!(
+            validate_position(&board(3, 3, "XXXOOO...")),
+            invalid("Both players have a line")
+        );
+    }
+
+    #[test]
+    fn rejects_play_after_a_win() {
+        assert_eq!(
+            validate_position(&board(3, 3, "XXXOO...O")),
+            invalid("O played after X won")
+        );
+        assert_eq!(
+            validate_position(&board(3, 3, "OOOXX.X.X")),
+            invalid("X played after O won")
+        );
+        // Each line of X was complete before the other was finished.
+        assert_eq!(
+            validate_position(&board(7, 3, "XXX.XXXOO.OO.O")),
+            invalid("X kept playing after winning")
+        );
+    }
+
+    #[test]
+    fn rejects_bad_symbol_counts() {
+        assert_eq!(
+            validate_position(&board(3, 3, "XX.......")),
+            invalid("X has 2 symbols and O 0, but X starts and they take turns")
+        );
+        assert_eq!(
+            validate_position(&board(3, 3, "O........")),
+            invalid("X has 0 symbols and O 1, but X starts and they take turns")
+        );
+    }
+}
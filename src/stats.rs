@@ -1,4 +1,4 @@
-use std::{fmt::Display, ops::AddAssign};
+use std::{collections::HashMap, fmt::Display, ops::AddAssign};
 
 #[derive(Copy, Clone)]
 pub struct Results {
@@ -66,4 +66,22 @@ impl AddAssign<(usize, usize, usize)> for Stat {
         self.draws += rhs.1;
         self.losts += rhs.2;
     }
-}
\ No newline at end of file
+}
+
+/// Adds the outcome of a game between `x` and `o` to `results`.
+pub fn record(results: &mut HashMap<String, Results>, x: &str, o: &str, winner: Option<u8>) {
+    let (x_result, o_result) = match winner {
+        Some(b'X') => ((1, 0, 0), (0, 0, 1)),
+        Some(_) => ((0, 0, 1), (1, 0, 0)),
+        None => ((0, 1, 0), (0, 1, 0)),
+    };
+
+    results
+        .entry(x.to_string())
+        .or_insert_with(Results::new)
+        .playing_x += x_result;
+    results
+        .entry(o.to_string())
+        .or_insert_with(Results::new)
+        .playing_o += o_result;
+}
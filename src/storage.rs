@@ -0,0 +1,62 @@
+use crate::error::Error;
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// Where models, checkpoints and tablebases are kept unless `Storage::new`
+/// picks another directory.
+pub const DEFAULT_MODELS_DIR: &str = "data";
+
+/// The directory models, checkpoints and tablebases are kept in, given to
+/// everything that reads or saves them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Storage {
+    dir: PathBuf,
+}
+
+impl Storage {
+    /// Keeps models, checkpoints and tablebases in `dir`.
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// The path of the file `name` in the directory, which must exist.
+    pub(crate) fn path(&self, name: &str) -> Result<PathBuf, Error> {
+        if !self.dir.is_dir() {
+            return Err(Error::MissingModelsDir(self.dir.clone()));
+        }
+        Ok(self.dir.join(name))
+    }
+}
+
+impl Default for Storage {
+    fn default() -> Self {
+        Self::new(DEFAULT_MODELS_DIR)
+    }
+}
+
+/// Replaces the file at `path` with `bytes` at once: they are written to a
+/// temporary file next to it, which is then renamed over it, so that an
+/// interrupted save leaves the previous file whole.
+pub(crate) fn write_atomic(path: &Path, bytes: &[u8]) -> Result<(), Error> {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(format!(".{}.tmp", std::process::id()));
+    let tmp = PathBuf::from(tmp);
+
+    let written = File::create(&tmp).and_then(|mut file| {
+        file.write_all(bytes)?;
+        file.sync_all()
+    });
+
+    match written.and_then(|_| fs::rename(&tmp, path)) {
+        Ok(()) => Ok(()),
+        Err(e) => {
+            let _ = fs::remove_file(&tmp);
+            Err(Error::Io(e))
+        }
+    }
+}
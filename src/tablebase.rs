@@ -0,0 +1,352 @@
+use crate::error::Error;
+use crate::game::Board;
+use crate::player::Player;
+use crate::seed;
+use crate::states::enumerate_positions;
+use crate::storage::{self, Storage};
+use rand::{rngs::StdRng, seq::SliceRandom};
+use std::collections::HashMap;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+
+/// Marks tablebase files, followed by a format version.
+const MAGIC: &[u8; 4] = b"TTTB";
+const VERSION: u8 = 1;
+
+/// The result of a position for the player about to move, with perfect play
+/// on both sides.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Outcome {
+    Win,
+    Draw,
+    Loss,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Solution {
+    pub outcome: Outcome,
+    /// How many moves remain until the game ends, the winner going for the
+    /// quickest win and the other side holding out as long as it can.
+    pub distance: u8,
+}
+
+impl Solution {
+    /// Packs the entry in a byte: the outcome in the two high bits and the
+    /// distance in the others.
+    fn to_byte(self) -> u8 {
+        let outcome = match self.outcome {
+            Outcome::Win => 0,
+            Outcome::Draw => 1,
+            Outcome::Loss => 2,
+        };
+        outcome << 6 | self.distance
+    }
+
+    fn from_byte(byte: u8) -> Option<Self> {
+        let outcome = match byte >> 6 {
+            0 => Outcome::Win,
+            1 => Outcome::Draw,
+            2 => Outcome::Loss,
+            _ => return None,
+        };
+        Some(Self {
+            outcome,
+            distance: byte & 0x3f,
+        })
+    }
+}
+
+/// The outcome of every position reachable on a board, indexed by
+/// `GameState::get_index`, so only boards of up to 20 cells have one.
+pub struct Tablebase {
+    width: usize,
+    height: usize,
+    win_length: usize,
+    entries: HashMap<u32, Solution>,
+}
+
+impl Tablebase {
+    /// Solves every position reachable from `board` by retrograde analysis:
+    /// finished games are scored first, then positions with one more empty
+    /// cell from the positions they lead to, and so on back to `board`.
+    pub fn generate(board: &Board) -> Result<Self, Error> {
+        if board.get_index().is_none() {
+            return Err(Error::Tablebase(format!(
+                "A {}x{} board is too large for a tablebase",
+                board.width(),
+                board.height()
+            )));
+        }
+
+        let mut positions: Vec<Board> = enumerate_positions(board)
+            .into_iter()
+            .map(|p| p.board)
+            .collect();
+        positions.sort_by_key(|b| b.get_available().len());
+
+        let mut entries = HashMap::with_capacity(positions.len());
+
+        for board in &positions {
+            let entry = match board.get_winner() {
+                // The opponent made the last move, so a line is theirs.
+                (true, Some(_)) => Solution {
+                    outcome: Outcome::Loss,
+                    distance: 0,
+                },
+                (true, None) => Solution {
+                    outcome: Outcome::Draw,
+                    distance: 0,
+                },
+                (false, _) => {
+                    let turn = board.get_turn();
+                    let children = board.get_available().into_iter().filter_map(|position| {
+                        let mut next = board.clone();
+                        next.play_move(position, turn).ok()?;
+                        entries.get(&next.get_index()?).copied()
+                    });
+                    best_reply(children)
+                }
+            };
+            entries.insert(board.get_index().unwrap(), entry);
+        }
+
+        Ok(Self {
+            width: board.width(),
+            height: board.height(),
+            win_length: board.win_length(),
+            entries,
+        })
+    }
+
+    /// Loads the tablebase of `board` from `storage`, generating and saving
+    /// it first if there is none yet.
+    pub fn load_or_generate(board: &Board, storage: &Storage) -> Result<Self, Error> {
+        match Self::load(board, storage) {
+            Err(Error::Io(e)) if e.kind() == ErrorKind::NotFound => {
+                println!("Generating the tablebase, this only happens once");
+                let tablebase = Self::generate(&Board::with_size(
+                    board.width(),
+                    board.height(),
+                    board.win_length(),
+                )?)?;
+                tablebase.save(storage)?;
+                Ok(tablebase)
+            }
+            result => result,
+        }
+    }
+
+    /// Writes the tablebase to `storage` as a small header followed by the
+    /// index and packed entry of every position.
+    pub fn save(&self, storage: &Storage) -> Result<(), Error> {
+        let filename = filename(self.width, self.height, self.win_length, storage)?;
+
+        let mut indices: Vec<&u32> = self.entries.keys().collect();
+        indices.sort_unstable();
+
+        let mut bytes = Vec::with_capacity(12 + 5 * indices.len());
+        bytes.extend_from_slice(MAGIC);
+        bytes.extend_from_slice(&[
+            VERSION,
+            self.width as u8,
+            self.height as u8,
+            self.win_length as u8,
+        ]);
+        bytes.extend_from_slice(&(indices.len() as u32).to_le_bytes());
+        for index in indices {
+            bytes.extend_from_slice(&index.to_le_bytes());
+            bytes.push(self.entries[index].to_byte());
+        }
+
+        storage::write_atomic(&filename, &bytes)?;
+        println!(
+            "Successfully saved {} positions to {}",
+            self.entries.len(),
+            filename.display()
+        );
+
+        Ok(())
+    }
+
+    /// Loads the tablebase of `board` from `storage`.
+    pub fn load(board: &Board, storage: &Storage) -> Result<Self, Error> {
+        let filename = filename(board.width(), board.height(), board.win_length(), storage)?;
+        let bytes = fs::read(&filename)?;
+
+        let invalid =
+            || Error::Tablebase(format!("{} is not a valid tablebase", filename.display()));
+
+        if bytes.len() < 12 || &bytes[..4] != MAGIC || bytes[4] != VERSION {
+            return Err(invalid());
+        }
+        let size = (bytes[5] as usize, bytes[6] as usize, bytes[7] as usize);
+        if size != (board.width(), board.height(), board.win_length()) {
+            return Err(invalid());
+        }
+
+        let count = u32::from_le_bytes(bytes[8..12].try_into().unwrap()) as usize;
+        let records = &bytes[12..];
+        if records.len() != 5 * count {
+            return Err(invalid());
+        }
+
+        let entries = records
+            .chunks_exact(5)
+            .map(|r| {
+                let index = u32::from_le_bytes(r[..4].try_into().unwrap());
+                Solution::from_byte(r[4]).map(|entry| (index, entry))
+            })
+            .collect::<Option<HashMap<_, _>>>()
+            .ok_or_else(invalid)?;
+
+        Ok(Self {
+            width: size.0,
+            height: size.1,
+            win_length: size.2,
+            entries,
+        })
+    }
+
+    /// The outcome of `board` for the player to move, `None` if the position
+    /// cannot be reached or the board is not the one of the tablebase.
+    pub fn lookup(&self, board: &Board) -> Option<Solution> {
+        if (board.width(), board.height(), board.win_length())
+            != (self.width, self.height, self.win_length)
+        {
+            return None;
+        }
+        self.entries.get(&board.get_index()?).copied()
+    }
+
+    /// The moves keeping the best outcome for the player to move on `board`,
+    /// winning as fast or losing as slowly as possible.
+    pub fn best_moves(&self, board: &Board) -> Vec<usize> {
+        let turn = board.get_turn();
+        let replies: Vec<(usize, Solution)> = board
+            .get_available()
+            .into_iter()
+            .filter_map(|position| {
+                let mut next = board.clone();
+                next.play_move(position, turn).ok()?;
+                Some((position, self.lookup(&next)?))
+            })
+            .collect();
+
+        let best = best_reply(replies.iter().map(|&(_, entry)| entry));
+        replies
+            .into_iter()
+            .filter(|&(_, entry)| flip(entry) == best)
+            .map(|(position, _)| position)
+            .collect()
+    }
+}
+
+/// Where the tablebase of boards of this size is stored.
+fn filename(
+    width: usize,
+    height: usize,
+    win_length: usize,
+    storage: &Storage,
+) -> Result<PathBuf, Error> {
+    storage.path(&format!("tablebase_{}x{}x{}", width, height, win_length))
+}
+
+/// The entry of a position whose moves lead to `children`, each scored for
+/// the opponent.
+fn best_reply(children: impl Iterator<Item = Solution>) -> Solution {
+    children
+        .map(flip)
+        .max_by_key(|entry| match entry.outcome {
+            Outcome::Win => (2, -(entry.distance as i32)),
+            Outcome::Draw => (1, entry.distance as i32),
+            Outcome::Loss => (0, entry.distance as i32),
+        })
+        .expect("an unfinished game has a legal move")
+}
+
+/// Turns the entry of a position after a move into the entry for the player
+/// who made it, one move further from the end.
+fn flip(entry: Solution) -> Solution {
+    Solution {
+        outcome: match entry.outcome {
+            Outcome::Win => Outcome::Loss,
+            Outcome::Draw => Outcome::Draw,
+            Outcome::Loss => Outcome::Win,
+        },
+        distance: entry.distance + 1,
+    }
+}
+
+/// A perfect player reading its moves from a tablebase, picking at random
+/// among the equally good ones.
+pub struct TablebasePlayer {
+    tablebase: Tablebase,
+    rng: StdRng,
+}
+
+impl TablebasePlayer {
+    /// Creates a player choosing between equally good moves with a generator
+    /// derived from `seed`, or from entropy without one.
+    pub fn new(tablebase: Tablebase, seed: Option<u64>) -> Self {
+        Self {
+            tablebase,
+            rng: seed::rng(seed::derive(seed, "tablebase")),
+        }
+    }
+}
+
+impl Player for TablebasePlayer {
+    fn choose_move(&mut self, board: &Board, _symbol: u8) -> usize {
+        let moves = match self.tablebase.best_moves(board) {
+            moves if moves.is_empty() => board.get_available(),
+            moves => moves,
+        };
+        *moves.choose(&mut self.rng).unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mmagent::{Minimax, MinimaxConfig};
+
+    #[test]
+    fn empty_3x3_is_a_draw_in_nine() {
+        let board = Board::new();
+        let tablebase = Tablebase::generate(&board).unwrap();
+        assert_eq!(
+            tablebase.lookup(&board),
+            Some(Solution {
+                outcome: Outcome::Draw,
+                distance: 9,
+            })
+        );
+    }
+
+    #[test]
+    fn best_moves_agree_with_minimax() {
+        let board = Board::new();
+        let tablebase = Tablebase::generate(&board).unwrap();
+        let mut minimax = Minimax::new(MinimaxConfig {
+            prefer_fast_wins: true,
+            seed: Some(0),
+            ..Default::default()
+        });
+
+        for position in enumerate_positions(&board) {
+            if position.terminal {
+                continue;
+            }
+            let best = tablebase.best_moves(&position.board);
+            let chosen = minimax.minimax_search(&position.board, position.turn);
+            assert!(
+                best.contains(&chosen),
+                "minimax plays {} on {}, the tablebase {:?}",
+                chosen,
+                position.board.to_notation(),
+                best
+            );
+        }
+    }
+}
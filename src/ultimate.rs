@@ -0,0 +1,266 @@
+use crate::game::{Board, BoardError, GameState};
+use std::fmt;
+use std::fmt::Formatter;
+
+/// Ultimate tic-tac-toe: nine 3x3 boards arranged in a 3x3 grid.
+///
+/// Positions are indices into the full 9x9 grid, read row by row. The cell
+/// played inside a sub-board sends the opponent to the sub-board at the same
+/// place in the grid, unless that one is already won or full, in which case
+/// any open sub-board may be played. Winning three sub-boards in a row wins
+/// the game.
+#[derive(Clone)]
+pub struct UltimateBoard {
+    boards: Vec<Board>,
+    meta: Board,
+    next: Option<usize>,
+}
+
+impl UltimateBoard {
+    pub fn new() -> Self {
+        Self {
+            boards: vec![Board::new(); 9],
+            meta: Board::new(),
+            next: None,
+        }
+    }
+
+    /// The sub-board the next move must be played in, `None` if any open one
+    /// is allowed.
+    pub fn next_board(&self) -> Option<usize> {
+        self.next
+    }
+
+    /// Whether sub-board `index` is won or full and cannot be played anymore.
+    pub fn is_closed(&self, index: usize) -> bool {
+        self.boards[index].get_winner().0
+    }
+
+    /// Splits a position on the 9x9 grid into its sub-board and the cell
+    /// inside it.
+    fn split(position: usize) -> (usize, usize) {
+        let (row, col) = (position / 9, position % 9);
+        ((row / 3) * 3 + col / 3, (row % 3) * 3 + col % 3)
+    }
+
+    fn join(board: usize, cell: usize) -> usize {
+        let row = (board / 3) * 3 + cell / 3;
+        let col = (board % 3) * 3 + cell % 3;
+        row * 9 + col
+    }
+
+    fn count_symbol(&self, symbol: u8) -> usize {
+        self.boards
+            .iter()
+            .flat_map(|b| b.symbols().iter())
+            .filter(|&&c| c == symbol)
+            .count()
+    }
+}
+
+impl GameState for UltimateBoard {
+    fn cells(&self) -> usize {
+        81
+    }
+
+    fn width(&self) -> usize {
+        9
+    }
+
+    fn variant(&self) -> String {
+        "ultimate".to_string()
+    }
+
+    fn get_available(&self) -> Vec<usize> {
+        if self.get_winner().0 {
+            return vec![];
+        }
+
+        let boards: Vec<usize> = match self.next {
+            Some(b) => vec![b],
+            None => (0..9).filter(|&b| !self.is_closed(b)).collect(),
+        };
+
+        boards
+            .into_iter()
+            .flat_map(|b| {
+                self.boards[b]
+                    .get_available()
+                    .into_iter()
+                    .map(move |cell| Self::join(b, cell))
+            })
+            .collect()
+    }
+
+    fn play_move(&mut self, position: usize, player: u8) -> Result<(), BoardError> {
+        if player != b'X' && player != b'O' {
+            return Err(BoardError::InvalidSymbol(player));
+        }
+
+        if position >= 81 {
+            return Err(BoardError::OutOfRange(position));
+        }
+
+        if self.get_winner().0 {
+            return Err(BoardError::GameOver);
+        }
+
+        let turn = self.get_turn();
+        if player != turn {
+            return Err(BoardError::WrongTurn {
+                expected: turn,
+                found: player,
+            });
+        }
+
+        let (board, cell) = Self::split(position);
+
+        if let Some(next) = self.next {
+            if next != board {
+                return Err(BoardError::WrongBoard {
+                    expected: next,
+                    found: board,
+                });
+            }
+        }
+
+        if self.is_closed(board) {
+            return Err(BoardError::ClosedBoard(board));
+        }
+
+        if self.boards[board].get(cell) != b' ' {
+            return Err(BoardError::Occupied {
+                position,
+                by: self.boards[board].get(cell),
+            });
+        }
+
+        self.boards[board].set(cell, player);
+
+        if let (true, Some(winner)) = self.boards[board].get_winner() {
+            self.meta.set(board, winner);
+        }
+
+        self.next = if self.is_closed(cell) {
+            None
+        } else {
+            Some(cell)
+        };
+
+        Ok(())
+    }
+
+    fn get_winner(&self) -> (bool, Option<u8>) {
+        if let (true, Some(winner)) = self.meta.get_winner() {
+            return (true, Some(winner));
+        }
+
+        if (0..9).all(|b| self.is_closed(b)) {
+            return (true, None);
+        }
+
+        (false, None)
+    }
+
+    fn get_turn(&self) -> u8 {
+        if self.count_symbol(b'X') > self.count_symbol(b'O') {
+            b'O'
+        } else {
+            b'X'
+        }
+    }
+
+    fn get_hash(&self) -> String {
+        let mut hash: String = self
+            .boards
+            .iter()
+            .flat_map(|b| b.symbols().iter())
+            .map(|&c| c as char)
+            .collect();
+
+        hash.push(match self.next {
+            Some(b) => (b'1' + b as u8) as char,
+            None => '*',
+        });
+
+        hash
+    }
+
+    fn evaluate(&self, symbol: u8) -> i32 {
+        let won = |s: u8| self.meta.symbols().iter().filter(|&&c| c == s).count() as i32;
+        let other = if symbol == b'X' { b'O' } else { b'X' };
+
+        won(symbol) - won(other)
+    }
+}
+
+impl Default for UltimateBoard {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Display for UltimateBoard {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        writeln!(f)?;
+        for row in 0..9 {
+            if row != 0 && row % 3 == 0 {
+                writeln!(f, "-------+-------+-------")?;
+            }
+            for col in 0..9 {
+                if col != 0 && col % 3 == 0 {
+                    write!(f, " |")?;
+                }
+                let (board, cell) = Self::split(row * 9 + col);
+                let c = match self.boards[board].get(cell) {
+                    b' ' => '.',
+                    c => c as char,
+                };
+                write!(f, " {}", c)?;
+            }
+            writeln!(f)?;
+        }
+
+        match self.next {
+            Some(b) => writeln!(f, "Next move in board {}", b + 1),
+            None => writeln!(f, "Next move in any open board"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::tests::assert_rejected;
+
+    #[test]
+    fn rejects_move_outside_the_required_board() {
+        let mut board = UltimateBoard::new();
+        board.play_move(UltimateBoard::join(4, 0), b'X').unwrap();
+
+        assert_rejected(
+            &mut board,
+            UltimateBoard::join(1, 0),
+            b'O',
+            BoardError::WrongBoard {
+                expected: 0,
+                found: 1,
+            },
+        );
+    }
+
+    #[test]
+    fn rejects_move_in_a_closed_board() {
+        // X won the first sub-board and O may play in any open one.
+        let mut board = UltimateBoard::new();
+        board.boards[0] = "XXX/OO./...".parse().unwrap();
+        board.meta.set(0, b'X');
+
+        assert_rejected(
+            &mut board,
+            UltimateBoard::join(0, 5),
+            b'O',
+            BoardError::ClosedBoard(0),
+        );
+    }
+}